@@ -0,0 +1,174 @@
+use clap::ValueEnum;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::BufWriter,
+    path::Path,
+};
+
+use crate::{AnoMes, ChaveAcesso, SpedResult, fmt_milhares, get_unidade_federativa};
+
+/// Número máximo de grupos exibidos no terminal (o arquivo CSV contém todos).
+const MAX_GRUPOS_EXIBIDOS: usize = 20;
+
+/// Agrupamentos adicionais (além do modelo) das chaves encontradas e não encontradas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Agrupamento {
+    /// UF do emitente (cUF: posições 1-2 da chave)
+    Uf,
+    /// Raiz do CNPJ do emitente (8 primeiras posições do CNPJ) ou CPF completo
+    Emitente,
+    /// Ano e mês de emissão (AAMM: posições 3-6 da chave)
+    Aamm,
+}
+
+impl Agrupamento {
+    /// Nome do agrupamento utilizado no relatório e no nome do arquivo.
+    pub fn nome(&self) -> &'static str {
+        match self {
+            Agrupamento::Uf => "UF do Emitente",
+            Agrupamento::Emitente => "Raiz do CNPJ do Emitente",
+            Agrupamento::Aamm => "Ano e Mês de Emissão",
+        }
+    }
+
+    /// Código do grupo ao qual a chave pertence.
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::{Agrupamento, ChaveAcesso};
+    ///
+    /// let chave: ChaveAcesso = "35240112345678000190550010000012341123456786".parse().unwrap();
+    ///
+    /// assert_eq!(Agrupamento::Uf.grupo(&chave), "35");
+    /// assert_eq!(Agrupamento::Emitente.grupo(&chave), "12345678");
+    /// assert_eq!(Agrupamento::Aamm.grupo(&chave), "2401");
+    ///
+    /// // Emitente pessoa física: agrupado pelo CPF completo
+    /// let cpf: ChaveAcesso = "35240100012345678909550010000012341123456780".parse().unwrap();
+    /// assert_eq!(Agrupamento::Emitente.grupo(&cpf), "12345678909");
+    /// ```
+    pub fn grupo(&self, chave: &ChaveAcesso) -> String {
+        match self {
+            Agrupamento::Uf => format!("{:02}", chave.cuf()),
+            // O CPF não tem raiz: "000" + 5 dígitos do CPF não identificaria o emitente
+            Agrupamento::Emitente => chave
+                .cpf_emitente()
+                .unwrap_or_else(|| chave.emitente()[..8].to_string()),
+            Agrupamento::Aamm => format!("{:04}", chave.aamm()),
+        }
+    }
+
+    /// Descrição legível do grupo (ex: sigla da UF, raiz do CNPJ formatada, mês/ano).
+    fn descricao(&self, grupo: &str, chave: &ChaveAcesso) -> String {
+        match self {
+            Agrupamento::Uf => get_unidade_federativa(grupo).to_string(),
+            // O CPF tem 11 dígitos e a raiz do CNPJ, 8 posições ASCII
+            Agrupamento::Emitente if grupo.len() == 11 => format!(
+                "CPF {}.{}.{}-{}",
+                &grupo[..3],
+                &grupo[3..6],
+                &grupo[6..9],
+                &grupo[9..]
+            ),
+            Agrupamento::Emitente => format!("{}.{}.{}", &grupo[..2], &grupo[2..5], &grupo[5..]),
+            Agrupamento::Aamm => AnoMes::from_chave(chave)
+                .map(|aamm| aamm.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Contagem de chaves da EFD em um grupo.
+#[derive(Debug, Default)]
+struct Contagem {
+    descricao: String,
+    total: usize,
+    encontradas: usize,
+}
+
+/// Imprime e exporta a distribuição das chaves da EFD segundo o agrupamento escolhido.
+pub fn exportar_agrupamento(
+    agrupamento: Agrupamento,
+    keys_efd: &HashSet<ChaveAcesso>,
+    keys_doc: &HashSet<ChaveAcesso>,
+    nome_efd: &str,
+    target_base: &Path,
+) -> SpedResult<()> {
+    // 1. Agrupamento funcional: Grupo -> Contagem
+    let hash_seg = keys_efd
+        .iter()
+        .fold(BTreeMap::<String, Contagem>::new(), |mut acc, chave| {
+            let grupo = agrupamento.grupo(chave);
+            let contagem = acc.entry(grupo.clone()).or_default();
+
+            if contagem.descricao.is_empty() {
+                contagem.descricao = agrupamento.descricao(&grupo, chave);
+            }
+
+            contagem.total += 1;
+            if keys_doc.contains(chave) {
+                contagem.encontradas += 1;
+            }
+            acc
+        });
+
+    // 2. Ordenação: mais chaves não encontradas primeiro
+    let mut grupos: Vec<(&String, &Contagem)> = hash_seg.iter().collect();
+    grupos.sort_by_key(|(grupo, c)| (std::cmp::Reverse(c.total - c.encontradas), *grupo));
+
+    let file_path = format!(
+        "{}-Chaves por {}.csv",
+        target_base.display(),
+        agrupamento.nome()
+    );
+
+    println!(
+        " --- Chaves de {} por {} ({} grupos) ---",
+        nome_efd,
+        agrupamento.nome(),
+        fmt_milhares(grupos.len())
+    );
+
+    for (grupo, c) in grupos.iter().take(MAX_GRUPOS_EXIBIDOS) {
+        println!(
+            " {:<14} {:<14} : EFD = {:>9} ; encontradas = {:>9} ; não encontradas = {:>9}",
+            grupo,
+            c.descricao,
+            fmt_milhares(c.total),
+            fmt_milhares(c.encontradas),
+            fmt_milhares(c.total - c.encontradas)
+        );
+    }
+
+    if grupos.len() > MAX_GRUPOS_EXIBIDOS {
+        println!(" ... (relação completa no arquivo)");
+    }
+
+    println!(" ---> Novo arquivo de chaves por grupo: <{}>\n", file_path);
+
+    // 3. Exportação em CSV
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(BufWriter::new(File::create(&file_path)?));
+
+    wtr.write_record([
+        agrupamento.nome(),
+        "Descrição",
+        "Chaves na EFD",
+        "Encontradas",
+        "Não Encontradas",
+    ])?;
+
+    for (grupo, c) in grupos {
+        wtr.write_record([
+            grupo.as_str(),
+            &c.descricao,
+            &c.total.to_string(),
+            &c.encontradas.to_string(),
+            &(c.total - c.encontradas).to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
@@ -1,21 +1,119 @@
 use clap::Parser;
 use rand::Rng;
+use regex::Regex;
 use std::{
     collections::{HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
 };
 
-use crate::{COLUNAS_DOC, COLUNAS_EFD, REGEX_SEARCH_CSV, SpedError, SpedResult};
+use crate::{
+    Agrupamento, AlvoDoGrafo, ArquivoEfd, COLUNAS_DOC, COLUNAS_EFD, COLUNAS_EFD_ICMS_IPI,
+    Codificacao, Conciliacao, EscrituracaoEfd, FaixaDeCodigos, FiltrosEfd, IndiceDeRelacoes,
+    IntervaloDePeriodos, RE_NON_ALPHANUMERIC, REGEX_SEARCH_CSV, Regra, RegrasDeInclusao, SpedError,
+    SpedResult, TipoDeArquivo, Valor, eh_sped_efd_txt,
+};
 
 // Estrutura para o Clap processar os argumentos da linha de comando
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Arguments {
+    /// Agrupar as chaves encontradas e não encontradas (além do modelo).
+    ///
+    /// Valores aceitos (separados por vírgula): uf, emitente, aamm.
+    ///
+    /// Exemplo: `--agrupar uf,emitente`
+    #[arg(long, value_enum, value_delimiter = ',')]
+    agrupar: Vec<Agrupamento>,
+
     /// Clear screen
     #[arg(short, long, default_value_t = false)]
     clear: bool,
 
+    /// Procurar correções prováveis para as chaves não encontradas.
+    ///
+    /// Chaves da EFD ausentes nos Documentos Fiscais (ou com DV inválido) são comparadas
+    /// com todas as chaves dos Documentos Fiscais: um dígito divergente ou
+    /// dois dígitos adjacentes trocados.
+    #[arg(long, default_value_t = false)]
+    corrigir_chaves: bool,
+
+    /// Conciliar os valores da EFD com os valores dos Documentos Fiscais.
+    ///
+    /// Para cada chave declarada na EFD e encontrada nos Documentos Fiscais, compara o
+    /// valor dos itens e dos tributos (PIS/PASEP, COFINS e ICMS; na EFD ICMS/IPI,
+    /// base de cálculo do ICMS, ICMS, ICMS ST e IPI).
+    ///
+    /// Se a chave consta de mais de um arquivo da EFD (ex: original e retificadora),
+    /// prevalecem os valores do último arquivo informado em `--efd-path`.
+    #[arg(long, default_value_t = false)]
+    conciliar: bool,
+
+    /// Conciliar os valores por item (chave e número do item), em vez de por chave.
+    #[arg(long, default_value_t = false, requires = "conciliar")]
+    conciliar_itens: bool,
+
+    /// Diferença máxima aceita na conciliação de valores (ex: `0,05`).
+    #[arg(
+        long,
+        default_value = "0,01",
+        value_name = "VALOR",
+        requires = "conciliar"
+    )]
+    tolerancia: Valor,
+
+    /// Arquivo de relações CTe -> NFes.
+    ///
+    /// Cada linha contém a chave do CTe seguida das chaves das NFes transportadas.
+    /// Se omitido, é lido o arquivo `cte_nfes.txt` do diretório atual, se existir.
+    /// Use `none` para desativar apenas este arquivo.
+    #[arg(long, value_name = "ARQUIVO")]
+    cte_nfes: Option<PathBuf>,
+
+    /// Arquivo de relações CTe -> CTe complementar (transporte subcontratado).
+    ///
+    /// Cada linha contém a chave do CTe seguida da chave do CTe complementar.
+    /// Se omitido, é lido o arquivo `transporte_subcontratado-chaves_complementares_dos_CTes.txt`
+    /// do diretório atual, se existir. Use `none` para desativar apenas este arquivo.
+    #[arg(long, value_name = "ARQUIVO")]
+    cte_complementar: Option<PathBuf>,
+
+    /// Gravar as relações obtidas dos XMLs de CT-e nos arquivos de texto
+    /// indicados em `--cte-nfes` e `--cte-complementar`, para reuso.
+    #[arg(long, default_value_t = false, requires = "xml_cte")]
+    gravar_relacoes: bool,
+
+    /// Desativar regras de inclusão de chaves no filtro.
+    ///
+    /// Valores aceitos (separados por vírgula):
+    /// a (chave-direta), b (cte-nfe-unica), c (nfe-via-cte), d (cte-complementar),
+    /// e (nfe-referenciada).
+    ///
+    /// Exemplo: `--desativar-regras b,d`
+    #[arg(long, value_enum, value_delimiter = ',')]
+    desativar_regras: Vec<Regra>,
+
+    /// Gravar as linhas descartadas dos arquivos de relações (`--cte-nfes`,
+    /// `--cte-complementar` e `--nfe-referenciadas`), com o número da linha
+    /// e o motivo do descarte.
+    #[arg(long, default_value_t = false)]
+    gravar_rejeitadas: bool,
+
+    /// Arquivo de relações NFe -> NFes referenciadas (`refNFe`), opcional.
+    ///
+    /// Cada linha contém a chave da NFe (ex: devolução) seguida das chaves das
+    /// NFes referenciadas (ex: venda original). Com `--xml-nfe`, as referências
+    /// também são obtidas dos próprios XMLs.
+    #[arg(long, value_name = "ARQUIVO")]
+    nfe_referenciadas: Option<PathBuf>,
+
+    /// Exportar o grafo de relações entre chaves (Graphviz DOT e GraphML).
+    ///
+    /// Valores aceitos: `todos` (todos os componentes conectados) ou uma chave
+    /// de 44 posições (apenas o componente conectado que contém a chave).
+    #[arg(long, value_name = "ALVO")]
+    grafo: Option<AlvoDoGrafo>,
+
     /// Imprimir chaves contidas em Documentos Fiscais
     #[arg(long, default_value_t = false)]
     docs_keys: bool,
@@ -24,13 +122,135 @@ struct Arguments {
     #[arg(long, default_value_t = false)]
     efd_keys: bool,
 
-    /// Arquivo da EFD Contribuições.
+    /// Arquivo(s) da EFD Contribuições.
     ///
-    /// Arquivo esperado:
+    /// Arquivos esperados:
     ///
     /// - `Info do Contribuinte EFD Contribuicoes.csv`
-    #[arg(short, long, required = true)]
-    efd_path: Option<PathBuf>,
+    ///
+    /// - Arquivo original da EFD Contribuições (SPED `.txt`, iniciado pelo registro 0000),
+    ///   convertido automaticamente para o formato CSV.
+    ///
+    /// Aceita vários arquivos (ex: um por ano, incluindo retificadoras) e padrões
+    /// com `*` e `?` no nome do arquivo (ex: `-e 'EFD/*.csv'`).
+    /// As chaves de todos os arquivos são reunidas em uma única auditoria.
+    #[arg(short, long, required = true, num_args = 1..)]
+    efd_path: Vec<PathBuf>,
+
+    /// Codificação dos arquivos de entrada (EFD, Documentos Fiscais e arquivos de relações).
+    ///
+    /// Valores aceitos: auto, utf-8, latin1 (iso-8859-1) e windows-1252 (cp1252).
+    /// No modo automático, arquivos com bytes inválidos em UTF-8 são lidos como Windows-1252.
+    /// Nos arquivos XML, o modo automático segue a declaração `<?xml ... encoding="..."?>`.
+    /// Os arquivos gerados são gravados em UTF-8 (ver `--manter-codificacao`).
+    #[arg(long = "encoding", value_enum, default_value_t = Codificacao::Auto)]
+    codificacao: Codificacao,
+
+    /// Gravar o arquivo final (Info da Receita sobre o Contribuinte) na codificação
+    /// original dos Documentos Fiscais, em vez de UTF-8.
+    #[arg(long, default_value_t = false)]
+    manter_codificacao: bool,
+
+    /// Escrituração digital dos arquivos da EFD.
+    ///
+    /// Valores aceitos: contribuicoes (EFD Contribuições) e icms-ipi (EFD ICMS/IPI).
+    ///
+    /// Exemplo: `--escrituracao icms-ipi -e 'Info do Contribuinte EFD ICMS IPI.csv'`
+    #[arg(long, value_enum, default_value_t = EscrituracaoEfd::Contribuicoes)]
+    escrituracao: EscrituracaoEfd,
+
+    /// Filtrar as linhas da EFD pelo Tipo de Crédito (códigos ou faixas, separados por vírgula).
+    /// Indisponível com o arquivo SPED (.txt) original.
+    ///
+    /// Exemplo: `--tipo-de-credito 101,102,201-299`
+    #[arg(long, value_delimiter = ',', value_name = "CODIGOS")]
+    tipo_de_credito: Vec<FaixaDeCodigos>,
+
+    /// Filtrar as linhas da EFD pelo CST (códigos ou faixas, separados por vírgula).
+    ///
+    /// Exemplo: `--cst 50-66` (operações com direito a crédito)
+    #[arg(long, value_delimiter = ',', value_name = "CODIGOS")]
+    cst: Vec<FaixaDeCodigos>,
+
+    /// Filtrar as linhas da EFD pela Natureza da Base de Cálculo dos Créditos
+    /// (códigos ou faixas, separados por vírgula). Indisponível na EFD ICMS/IPI
+    /// e com o arquivo SPED (.txt) original.
+    ///
+    /// Exemplo: `--natureza-bc 01-04,13`
+    #[arg(long, value_delimiter = ',', value_name = "CODIGOS")]
+    natureza_bc: Vec<FaixaDeCodigos>,
+
+    /// Filtrar as linhas da EFD pelo CFOP (códigos ou faixas, separados por vírgula).
+    ///
+    /// Exemplo: `--cfop 1000-2999`
+    #[arg(long, value_delimiter = ',', value_name = "CODIGOS")]
+    cfop: Vec<FaixaDeCodigos>,
+
+    /// Filtrar as linhas da EFD pelo Período de Apuração (`INICIO:FIM`, limites opcionais).
+    ///
+    /// Exemplos: `--periodo 01/2024:06/2024`, `--periodo 07/2024:` ou `--periodo 03/2024`
+    #[arg(long, value_name = "INICIO:FIM")]
+    periodo: Option<IntervaloDePeriodos>,
+
+    /// Filtrar as linhas da EFD pelo CNPJ do estabelecimento do contribuinte.
+    #[arg(long, value_name = "CNPJ")]
+    estabelecimento: Option<String>,
+
+    /// Procurar também documentos sem chave (ex: modelos 01 e 1B).
+    ///
+    /// As linhas da EFD sem chave de 44 dígitos são procuradas nos Documentos Fiscais
+    /// pelo CNPJ do participante e pelo número do documento fiscal.
+    #[arg(long, default_value_t = false)]
+    sem_chave: bool,
+
+    /// Obter relações CTe -> NFe dos próprios Documentos Fiscais.
+    ///
+    /// Os arquivos CSV são lidos previamente: a chave do CTe (Coluna 1) e a chave
+    /// da NFe (Coluna 2, `Inf. NFe - Chave de acesso da NF-e`) da mesma linha
+    /// são adicionadas às relações CTe -> NFes.
+    ///
+    /// A Coluna 2 também é utilizada na regra b): sem esta opção, apenas a
+    /// Coluna 1 dos Documentos Fiscais é lida.
+    #[arg(long, default_value_t = false)]
+    relacoes_dos_documentos: bool,
+
+    /// Não carregar as relações entre chaves: arquivos de relações entre CTes e NFes,
+    /// arquivo de NFes referenciadas (`--nfe-referenciadas`) e referências `refNFe`
+    /// dos XMLs de NF-e (`--xml-nfe`).
+    ///
+    /// A auditoria considera apenas as chaves declaradas na EFD.
+    #[arg(long, default_value_t = false)]
+    sem_relacoes: bool,
+
+    /// Verificar a consistência entre o AAMM da chave e as datas da EFD.
+    ///
+    /// Aponta as linhas cujo AAMM da chave difere da data de emissão, é posterior
+    /// à data de lançamento ou é muito anterior (ou posterior) ao período de apuração.
+    #[arg(long, default_value_t = false)]
+    verificar_periodos: bool,
+
+    /// Número máximo de meses entre o AAMM da chave e o período de apuração.
+    #[arg(long, default_value_t = 12, requires = "verificar_periodos")]
+    meses_defasagem: u32,
+
+    /// Diretório com arquivos XML de CT-e (`CTe`/`cteProc`) ou lotes `.zip`.
+    ///
+    /// As relações CTe -> NFes e CTe -> CTe complementar são obtidas diretamente
+    /// dos XMLs (em substituição aos arquivos de texto).
+    #[arg(long, conflicts_with = "sem_relacoes")]
+    xml_cte: Option<PathBuf>,
+
+    /// Diretório com arquivos XML de NF-e (`NFe`/`nfeProc`) ou lotes `.zip`.
+    ///
+    /// Cada item (`det`) das NF-e é analisado como uma linha de Documento Fiscal,
+    /// juntamente com os arquivos CSV do diretório atual (se houver).
+    #[arg(long)]
+    xml_nfe: Option<PathBuf>,
+
+    /// Limitar a inclusão de chaves correlacionadas às relações diretas (um salto),
+    /// sem o fecho transitivo dos CTes complementares.
+    #[arg(long, default_value_t = false)]
+    um_salto: bool,
 
     /// Ativar modo detalhado (verbose)
     #[arg(short, long, default_value_t = false)]
@@ -39,26 +259,102 @@ struct Arguments {
 
 #[derive(Debug)]
 pub struct Config {
+    pub agrupamentos: Vec<Agrupamento>,
     pub clear: bool,
+    pub corrigir_chaves: bool,
+    /// Conciliação de valores entre a EFD e os Documentos Fiscais (`None` sem `--conciliar`).
+    pub conciliacao: Option<Conciliacao>,
+    /// Arquivo de relações CTe -> NFes (`None` se desativado ou se o arquivo padrão não existe).
+    pub arquivo_cte_nfes: Option<PathBuf>,
+    /// Arquivo de relações CTe -> CTe complementar (`None` se desativado ou se o arquivo padrão não existe).
+    pub arquivo_cte_complementar: Option<PathBuf>,
+    /// Arquivo de relações NFe -> NFes referenciadas (`None` se não informado ou desativado).
+    pub arquivo_nfe_referenciadas: Option<PathBuf>,
+    /// Diretório de XMLs de NF-e do qual são obtidas as referências entre NFes
+    /// (`None` sem `--xml-nfe` ou com `--sem-relacoes`).
+    pub referencias_dos_xmls_de_nfe: Option<PathBuf>,
     pub docs_keys: bool,
     pub efd_keys: bool,
-    pub efd_path: PathBuf,
+    pub gravar_relacoes: bool,
+    /// Gravar o relatório de linhas descartadas dos arquivos de relações.
+    pub gravar_rejeitadas: bool,
+    /// Abrangência da exportação do grafo de relações (`None` se desativado).
+    pub grafo: Option<AlvoDoGrafo>,
+    /// Arquivos da EFD (CSV ou SPED `.txt` original), na ordem informada.
+    pub arquivos_efd: Vec<ArquivoEfd>,
+    /// Escrituração digital dos arquivos da EFD (Contribuições ou ICMS/IPI).
+    pub escrituracao: EscrituracaoEfd,
+    /// Filtros aplicados às linhas da EFD antes da extração das chaves.
+    pub filtros: FiltrosEfd,
+    /// Codificação dos arquivos de entrada (`Auto`: UTF-8 ou Windows-1252).
+    pub codificacao: Codificacao,
+    /// Gravar o arquivo final na codificação original dos Documentos Fiscais.
+    pub manter_codificacao: bool,
+    pub sem_chave: bool,
+    pub verificar_periodos: bool,
+    pub meses_defasagem: u32,
+    pub relacoes_dos_documentos: bool,
+    /// Regras de inclusão de chaves no filtro (a, b, c, d, e e um salto).
+    pub regras: RegrasDeInclusao,
+    /// Diretório com arquivos XML de CT-e (fonte alternativa das relações).
+    pub xml_cte: Option<PathBuf>,
     pub verbose: bool,
 
     // Lista de arquivos de suporte (NFes/CTes)
+    // Diretórios (opção `--xml-nfe`) contêm arquivos XML de NF-e
     pub arquivos_csv: Vec<PathBuf>,
 
     pub target: PathBuf,
     // Referências para os HasMaps estáticos
     pub colunas_efd: &'static HashMap<&'static str, &'static str>,
+    pub colunas_efd_icms_ipi: &'static HashMap<&'static str, &'static str>,
     pub colunas_doc: &'static HashMap<&'static str, &'static str>,
 
-    pub nfe_ctes: HashMap<String, HashSet<String>>,
-    pub cte_nfes: HashMap<String, HashSet<String>>,
-    pub cte_complementar: HashMap<String, HashSet<String>>,
+    /// Relações entre chaves (CTe -> NFes, componentes de CTes complementares e NFes referenciadas).
+    pub relacoes: IndiceDeRelacoes,
     pub total_de_itens_analisados: usize,
 }
 
+/// Configuração sem opções ativas (todas as regras de inclusão, sem arquivos).
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            agrupamentos: Vec::new(),
+            clear: false,
+            corrigir_chaves: false,
+            conciliacao: None,
+            arquivo_cte_nfes: None,
+            arquivo_cte_complementar: None,
+            arquivo_nfe_referenciadas: None,
+            referencias_dos_xmls_de_nfe: None,
+            docs_keys: false,
+            efd_keys: false,
+            gravar_relacoes: false,
+            gravar_rejeitadas: false,
+            grafo: None,
+            arquivos_efd: Vec::new(),
+            escrituracao: EscrituracaoEfd::default(),
+            filtros: FiltrosEfd::default(),
+            codificacao: Codificacao::default(),
+            manter_codificacao: false,
+            sem_chave: false,
+            verificar_periodos: false,
+            meses_defasagem: 12,
+            relacoes_dos_documentos: false,
+            regras: RegrasDeInclusao::default(),
+            xml_cte: None,
+            verbose: false,
+            arquivos_csv: Vec::new(),
+            target: PathBuf::new(),
+            colunas_efd: &COLUNAS_EFD,
+            colunas_efd_icms_ipi: &COLUNAS_EFD_ICMS_IPI,
+            colunas_doc: &COLUNAS_DOC,
+            relacoes: IndiceDeRelacoes::default(),
+            total_de_itens_analisados: 0,
+        }
+    }
+}
+
 impl Config {
     pub fn to_hash(&self, path: &Path) -> String {
         let hash = blake3::hash(path.display().to_string().as_bytes());
@@ -66,15 +362,104 @@ impl Config {
     }
 }
 
+/// Arquivos de relações lidos do diretório atual se `--cte-nfes` e `--cte-complementar`
+/// forem omitidos.
+const CTE_NFES: &str = "cte_nfes.txt";
+const CTE_COMPLEMENTAR: &str = "transporte_subcontratado-chaves_complementares_dos_CTes.txt";
+
+/// Valor de `--cte-nfes` e `--cte-complementar` que desativa apenas o arquivo da opção.
+const SEM_ARQUIVO: &str = "none";
+
+/// Arquivo de relações a ser lido (ou gravado, com `--gravar-relacoes`).
+///
+/// O arquivo padrão é considerado apenas se existir: o aviso de arquivo não encontrado
+/// (ver `carregar_relacoes`) fica restrito aos arquivos informados explicitamente.
+fn arquivo_de_relacoes(informado: Option<PathBuf>, padrao: &str, gravar: bool) -> Option<PathBuf> {
+    match informado {
+        Some(path) if path.as_os_str() == SEM_ARQUIVO => None,
+        Some(path) => Some(path),
+        None => Some(PathBuf::from(padrao)).filter(|path| gravar || path.is_file()),
+    }
+}
+
 pub fn get_config() -> SpedResult<Config> {
     let args = Arguments::parse();
 
     // 1. Extração funcional: Converte Option<PathBuf> em PathBuf ou retorna erro
     // Como o Clap já exige 'required = true', este erro só ocorreria em casos extremos.
-    let efd_path = args.efd_path.ok_or(SpedError::EfdFileNotFound)?;
+    let efd_paths = expandir_arquivos_efd(&args.efd_path)?;
+    if efd_paths.is_empty() {
+        return Err(SpedError::EfdFileNotFound);
+    }
+
+    // Formato de cada arquivo: o SPED `.txt` original é convertido apenas na EFD Contribuições
+    let arquivos_efd = efd_paths
+        .into_iter()
+        .map(|path| {
+            let tipo = match (args.escrituracao, eh_sped_efd_txt(&path)) {
+                (EscrituracaoEfd::Contribuicoes, true) => TipoDeArquivo::EFDContribTxt,
+                (EscrituracaoEfd::Contribuicoes, false) => TipoDeArquivo::EFDContrib,
+                (EscrituracaoEfd::IcmsIpi, true) => {
+                    return Err(SpedError::SpedTxtNaoSuportado { arquivo: path });
+                }
+                (EscrituracaoEfd::IcmsIpi, false) => TipoDeArquivo::EFDIcmsIpi,
+            };
+            Ok(ArquivoEfd {
+                path,
+                tipo,
+                codificacao: args.codificacao,
+            })
+        })
+        .collect::<SpedResult<Vec<_>>>()?;
+
+    // Filtros das linhas da EFD: as colunas devem existir no layout da escrituração
+    let filtros = FiltrosEfd {
+        tipos_de_credito: args.tipo_de_credito,
+        cst: args.cst,
+        naturezas_bc: args.natureza_bc,
+        cfop: args.cfop,
+        periodo: args.periodo,
+        estabelecimento: args
+            .estabelecimento
+            .as_deref()
+            .map(normalizar_cnpj)
+            .transpose()?,
+    };
+
+    if let Some(filtro) = filtros
+        .ativos()
+        .into_iter()
+        .find(|filtro| !args.escrituracao.colunas().contains_key(filtro.campo()))
+    {
+        return Err(SpedError::FiltroIndisponivel {
+            opcao: filtro.opcao(),
+            campo: filtro.campo(),
+            escrituracao: args.escrituracao.nome(),
+        });
+    }
+
+    // O arquivo SPED (.txt) convertido não preenche todas as colunas filtráveis
+    if let Some(arquivo) = arquivos_efd
+        .iter()
+        .find(|arquivo| matches!(arquivo.tipo, TipoDeArquivo::EFDContribTxt))
+        && let Some(filtro) = filtros
+            .ativos()
+            .into_iter()
+            .find(|filtro| !filtro.disponivel_no_sped_txt())
+    {
+        return Err(SpedError::FiltroIndisponivelNoSpedTxt {
+            opcao: filtro.opcao(),
+            campo: filtro.campo(),
+            arquivo: arquivo.path.clone(),
+        });
+    }
 
     // 2. Buscar arquivos CSV de NFes/CTes no diretório atual.
-    let arquivos_csv = search_csv_files(Path::new("."))?;
+    // Com `--xml-nfe`, os arquivos CSV são opcionais e o diretório de XMLs é incluído ao final.
+    let mut arquivos_csv = match (search_csv_files(Path::new(".")), &args.xml_nfe) {
+        (Err(SpedError::NoCSVFilesFound), Some(_)) => Vec::new(),
+        (resultado, _) => resultado?,
+    };
 
     // 3. Imprimir aqui (ou na main), mantendo a função de busca "pura"
     if !arquivos_csv.is_empty() {
@@ -85,6 +470,11 @@ pub fn get_config() -> SpedResult<Config> {
         println!();
     }
 
+    if let Some(dir) = &args.xml_nfe {
+        println!(" Diretório de arquivos XML de NFe: <{}>\n", dir.display());
+        arquivos_csv.push(dir.clone());
+    }
+
     // 4. Geração do Target (Funcional)
     let mut rng = rand::rng();
     let file_name = format!(
@@ -93,23 +483,67 @@ pub fn get_config() -> SpedResult<Config> {
     );
 
     Ok(Config {
+        agrupamentos: args.agrupar,
         clear: args.clear,
+        corrigir_chaves: args.corrigir_chaves,
+        conciliacao: args.conciliar.then_some(Conciliacao {
+            por_item: args.conciliar_itens,
+            tolerancia: args.tolerancia,
+        }),
+        arquivo_cte_nfes: arquivo_de_relacoes(args.cte_nfes, CTE_NFES, args.gravar_relacoes)
+            .filter(|_| !args.sem_relacoes),
+        arquivo_cte_complementar: arquivo_de_relacoes(
+            args.cte_complementar,
+            CTE_COMPLEMENTAR,
+            args.gravar_relacoes,
+        )
+        .filter(|_| !args.sem_relacoes),
+        arquivo_nfe_referenciadas: args.nfe_referenciadas.filter(|_| !args.sem_relacoes),
+        referencias_dos_xmls_de_nfe: args.xml_nfe.filter(|_| !args.sem_relacoes),
         docs_keys: args.docs_keys,
         efd_keys: args.efd_keys,
-        efd_path,
+        gravar_relacoes: args.gravar_relacoes,
+        gravar_rejeitadas: args.gravar_rejeitadas,
+        grafo: args.grafo,
+        arquivos_efd,
+        escrituracao: args.escrituracao,
+        filtros,
+        codificacao: args.codificacao,
+        manter_codificacao: args.manter_codificacao,
+        sem_chave: args.sem_chave,
+        verificar_periodos: args.verificar_periodos,
+        meses_defasagem: args.meses_defasagem,
+        relacoes_dos_documentos: args.relacoes_dos_documentos,
+        regras: RegrasDeInclusao::new(&args.desativar_regras, args.um_salto),
+        xml_cte: args.xml_cte,
         verbose: args.verbose,
         arquivos_csv,
         target: PathBuf::from(&file_name),
         // Apenas atribuímos as referências estáticas
         colunas_efd: &COLUNAS_EFD,
+        colunas_efd_icms_ipi: &COLUNAS_EFD_ICMS_IPI,
         colunas_doc: &COLUNAS_DOC,
-        nfe_ctes: HashMap::new(),
-        cte_nfes: HashMap::new(),
-        cte_complementar: HashMap::new(),
+        relacoes: IndiceDeRelacoes::default(),
         total_de_itens_analisados: 0,
     })
 }
 
+/// Remove os separadores do CNPJ (que pode conter letras), exigindo 14 posições.
+fn normalizar_cnpj(cnpj: &str) -> SpedResult<String> {
+    let normalizado = RE_NON_ALPHANUMERIC
+        .replace_all(cnpj, "")
+        .to_ascii_uppercase();
+
+    if normalizado.len() != 14 {
+        return Err(SpedError::InvalidCnpj {
+            cnpj: cnpj.to_string(),
+            length: normalizado.len(),
+        });
+    }
+
+    Ok(normalizado)
+}
+
 /// Procura arquivos CSV no diretório atual baseando-se nos padrões do ReceitaNet-BX.
 pub fn search_csv_files(dir: &std::path::Path) -> SpedResult<Vec<PathBuf>> {
     // 1. Leitura funcional do diretório
@@ -142,3 +576,67 @@ pub fn search_csv_files(dir: &std::path::Path) -> SpedResult<Vec<PathBuf>> {
 
     Ok(arquivos_csv)
 }
+
+/// Expande os padrões de `--efd-path` (`*` e `?` no nome do arquivo).
+///
+/// Caminhos sem curingas são mantidos; arquivos repetidos são descartados, comparando-se
+/// os caminhos canônicos (`efd.csv` e `./efd.csv` designam o mesmo arquivo).
+pub fn expandir_arquivos_efd(padroes: &[PathBuf]) -> SpedResult<Vec<PathBuf>> {
+    let mut arquivos: Vec<PathBuf> = Vec::new();
+    let mut canonicos: HashSet<PathBuf> = HashSet::new();
+    let mut incluir = |path: PathBuf| {
+        let canonico = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if canonicos.insert(canonico) {
+            arquivos.push(path);
+        }
+    };
+
+    for padrao in padroes {
+        let nome = padrao
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if !nome.contains(['*', '?']) {
+            incluir(padrao.clone());
+            continue;
+        }
+
+        // Curingas convertidos em expressão regular (apenas no nome do arquivo)
+        let regex = Regex::new(&format!(
+            "^{}$",
+            regex::escape(&nome)
+                .replace(r"\*", ".*")
+                .replace(r"\?", ".")
+        ))?;
+
+        let dir = match padrao.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let mut encontrados: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(SpedError::Io)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .is_some_and(|n| regex.is_match(n))
+            })
+            .collect();
+
+        if encontrados.is_empty() {
+            return Err(SpedError::EfdPatternNoMatch {
+                padrao: padrao.display().to_string(),
+            });
+        }
+
+        encontrados.sort();
+        encontrados.into_iter().for_each(&mut incluir);
+    }
+
+    Ok(arquivos)
+}
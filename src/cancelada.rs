@@ -0,0 +1,206 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ArquivoEfd, ChaveAcesso, Config, EscrituracaoEfd, SpedError, SpedResult, fmt_milhares,
+    leitor_efd, limpar_chave, localizar_coluna,
+};
+
+/// Situação de cancelamento informada na coluna `nota_cancelada`
+/// (`Cancelada : NF (Todos)` do ReceitaNet-BX).
+///
+/// O ReceitaNet-BX preenche a coluna com `Sim` ou `Não`; a coluna vazia indica
+/// documento sem registro de cancelamento. Retorna `None` para qualquer outro
+/// conteúdo, que deve ser informado ao usuário em vez de tratado como não cancelado.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::eh_cancelada;
+///
+/// assert_eq!(eh_cancelada("Sim"), Some(true));
+/// assert_eq!(eh_cancelada(" SIM "), Some(true));
+/// assert_eq!(eh_cancelada("Não"), Some(false));
+/// assert_eq!(eh_cancelada("Nao"), Some(false));
+/// assert_eq!(eh_cancelada(""), Some(false));
+/// assert_eq!(eh_cancelada("Cancelada"), None);
+/// ```
+pub fn eh_cancelada(situacao: &str) -> Option<bool> {
+    match situacao.trim().to_lowercase().as_str() {
+        "sim" => Some(true),
+        "não" | "nao" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// Imprime os conteúdos não reconhecidos da coluna `nota_cancelada`
+/// (ver `eh_cancelada`), com o número de linhas de cada um.
+pub fn imprimir_situacoes_nao_reconhecidas(situacoes: &BTreeMap<String, usize>) {
+    if situacoes.is_empty() {
+        return;
+    }
+
+    eprintln!(
+        " [AVISO] {} linhas dos Documentos Fiscais com situação de cancelamento não reconhecida (consideradas não canceladas):",
+        fmt_milhares(situacoes.values().sum())
+    );
+
+    for (situacao, num) in situacoes {
+        eprintln!("  {:>9} : {situacao:?}", fmt_milhares(*num));
+    }
+
+    eprintln!();
+}
+
+/// Linha da EFD com crédito apurado sobre documento fiscal cancelado.
+#[derive(Debug, Clone)]
+pub struct CreditoCancelado {
+    pub chave: ChaveAcesso,
+    /// Arquivo CSV da EFD.
+    pub arquivo: PathBuf,
+    /// Número da linha no arquivo CSV da EFD.
+    pub linha: usize,
+    /// Nº da Linha da EFD (arquivo SPED original).
+    pub efd_line: String,
+    pub periodo_apuracao: String,
+    pub codigo_cst: String,
+    /// Base de cálculo e valores do crédito (ver `EscrituracaoEfd::colunas_de_credito`).
+    pub valores: [String; 3],
+}
+
+/// Releitura da EFD para localizar as linhas cujas chaves
+/// se referem a documentos cancelados.
+///
+/// A releitura evita reter, durante toda a execução, os números de linha e valores
+/// de cada chave da EFD: apenas as linhas das chaves canceladas são guardadas.
+pub fn get_creditos_de_documentos_cancelados(
+    config: &Config,
+    canceladas: &HashSet<ChaveAcesso>,
+) -> SpedResult<Vec<CreditoCancelado>> {
+    if canceladas.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut creditos = Vec::new();
+
+    for arquivo in &config.arquivos_efd {
+        creditos.extend(creditos_do_arquivo(config, arquivo, canceladas)?);
+    }
+
+    Ok(creditos)
+}
+
+fn creditos_do_arquivo(
+    config: &Config,
+    arquivo: &ArquivoEfd,
+    canceladas: &HashSet<ChaveAcesso>,
+) -> SpedResult<Vec<CreditoCancelado>> {
+    let efd_path = arquivo.path.as_path();
+    let mut rdr = leitor_efd(efd_path, arquivo.codificacao)?;
+    let column_names: Vec<&str> = rdr.headers()?.iter().collect();
+
+    let localizar = |campo| localizar_coluna(&column_names, campo, arquivo.tipo, config, efd_path);
+
+    let idx_chave = localizar("chave_documento")?;
+    let idx_efd_line = localizar("efd_line")?;
+    let idx_periodo = localizar("periodo_apuracao")?;
+    let idx_cst = localizar("codigo_cst")?;
+    // Base de cálculo e valores do crédito, conforme a escrituração
+    let [base, valor_1, valor_2] = config.escrituracao.colunas_de_credito();
+    let idx_valores = [localizar(base)?, localizar(valor_1)?, localizar(valor_2)?];
+    // Apenas as linhas que atendem aos filtros da EFD
+    let filtros = config.filtros.localizar(localizar)?;
+
+    let mut creditos = Vec::new();
+
+    for (idx, result) in rdr.records().enumerate() {
+        let record = result.map_err(|e| SpedError::from_csv(e, efd_path.to_path_buf(), idx + 2))?;
+        let campo = |i: usize| record.get(i).unwrap_or_default().to_string();
+
+        if let Some(chave) = limpar_chave(record.get(idx_chave).unwrap_or_default())
+            && canceladas.contains(&chave)
+            && filtros.excluir(&record).is_none()
+        {
+            creditos.push(CreditoCancelado {
+                chave,
+                arquivo: efd_path.to_path_buf(),
+                linha: idx + 2,
+                efd_line: campo(idx_efd_line),
+                periodo_apuracao: campo(idx_periodo),
+                codigo_cst: campo(idx_cst),
+                valores: idx_valores.map(campo),
+            });
+        }
+    }
+
+    Ok(creditos)
+}
+
+/// Imprime o resumo e exporta os créditos em `<target>-Créditos sobre Documentos Cancelados.csv`.
+pub fn exportar_creditos_de_documentos_cancelados(
+    creditos: &[CreditoCancelado],
+    escrituracao: EscrituracaoEfd,
+    target_base: &Path,
+) -> SpedResult<()> {
+    let chaves: HashSet<ChaveAcesso> = creditos.iter().map(|c| c.chave).collect();
+
+    println!(
+        " Número de chaves da EFD referentes a documentos cancelados: {} ({} linhas da EFD)\n",
+        fmt_milhares(chaves.len()),
+        fmt_milhares(creditos.len())
+    );
+
+    if creditos.is_empty() {
+        return Ok(());
+    }
+
+    let file_path = format!(
+        "{}-Créditos sobre Documentos Cancelados.csv",
+        target_base.display()
+    );
+
+    println!(
+        " ---> Novo arquivo de créditos sobre documentos cancelados: <{}>\n",
+        file_path
+    );
+
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(BufWriter::new(File::create(&file_path)?));
+
+    let colunas = escrituracao.colunas();
+    let [base, valor_1, valor_2] = escrituracao.colunas_de_credito().map(|c| colunas[c]);
+
+    wtr.write_record([
+        "Arquivo da EFD",
+        "Linha",
+        "Nº da Linha da EFD",
+        "Período de Apuração",
+        "Chave",
+        "Modelo",
+        "CST",
+        base,
+        valor_1,
+        valor_2,
+    ])?;
+
+    for credito in creditos {
+        wtr.write_record([
+            &credito.arquivo.display().to_string(),
+            &credito.linha.to_string(),
+            &credito.efd_line,
+            &credito.periodo_apuracao,
+            &credito.chave.to_string(),
+            &credito.chave.modelo().to_string(),
+            &credito.codigo_cst,
+            &credito.valores[0],
+            &credito.valores[1],
+            &credito.valores[2],
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,410 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use crate::{RE_CHAVE_44, SpedError, get_modelo_documentos_fiscais};
+
+/// Chave de acesso de 44 posições de um Documento Fiscal Eletrônico (NFe, CTe, etc).
+///
+/// A partir de julho de 2026, o CNPJ do emitente pode ser alfanumérico: as 12 primeiras
+/// posições do CNPJ (posições 07..18 da chave) admitem letras maiúsculas.
+///
+/// ### Composição da chave
+/// | Posição | Tamanho | Campo                                 |
+/// |---------|---------|---------------------------------------|
+/// | 01..02  | 2       | cUF: código da UF do emitente         |
+/// | 03..06  | 4       | AAMM: ano e mês de emissão            |
+/// | 07..20  | 14      | CNPJ/CPF do emitente (alfanumérico)   |
+/// | 21..22  | 2       | Modelo do documento fiscal            |
+/// | 23..25  | 3       | Série                                 |
+/// | 26..34  | 9       | Número do documento fiscal            |
+/// | 35      | 1       | tpEmis: forma de emissão              |
+/// | 36..43  | 8       | cNF: código numérico                  |
+/// | 44      | 1       | DV: dígito verificador                |
+///
+/// ### Representação compacta
+/// Para reduzir o consumo de memória em auditorias com dezenas de milhões de chaves,
+/// a chave é armazenada em 22 bytes (em vez de uma `String` de 44 caracteres alocada no heap):
+/// - 11 bytes: posições 01..18 (6 dígitos seguidos de 12 caracteres na base 36);
+/// - 11 bytes: posições 19..44 (26 dígitos).
+///
+/// Ambas as partes são gravadas em big-endian, de modo que a ordenação dos bytes
+/// coincide com a ordenação alfabética da chave. Os campos numéricos são obtidos
+/// aritmeticamente da forma compacta; a conversão para texto ocorre apenas na saída.
+///
+/// ### Exemplo
+/// ```
+/// use reter_linhas_com_info_das_chaves::ChaveAcesso;
+///
+/// let chave: ChaveAcesso = "35240112345678000190550010000012341123456786".parse().unwrap();
+///
+/// assert_eq!(chave.cuf(), 35);
+/// assert_eq!(chave.aamm(), 2401);
+/// assert_eq!(chave.emitente(), "12345678000190");
+/// assert_eq!(chave.modelo().to_string(), "55");
+/// assert_eq!(chave.serie(), 1);
+/// assert_eq!(chave.numero(), 1234);
+/// assert_eq!(chave.tp_emis(), 1);
+/// assert_eq!(chave.cnf(), 12345678);
+/// assert_eq!(chave.dv(), 6);
+///
+/// let alfanumerica: ChaveAcesso = "35260712ABC34501DE35550010000012341123456799".parse().unwrap();
+///
+/// assert_eq!(alfanumerica.emitente(), "12ABC34501DE35");
+/// assert!(alfanumerica.dv_valido());
+/// assert_eq!(alfanumerica.to_string(), "35260712ABC34501DE35550010000012341123456799");
+/// assert_eq!(std::mem::size_of::<ChaveAcesso>(), 22);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChaveAcesso([u8; 22]);
+
+/// Número de bytes de cada metade da representação compacta.
+const METADE: usize = 11;
+
+/// 36^12: base do CNPJ alfanumérico (12 posições de 0-9 e A-Z).
+const BASE_CNPJ: u128 = 36u128.pow(12);
+
+impl ChaveAcesso {
+    /// Converte 44 caracteres validados (dígitos e letras maiúsculas) na forma compacta.
+    pub(crate) fn compactar(texto: &[u8]) -> ChaveAcesso {
+        let valor = |c: &u8| match c {
+            b'0'..=b'9' => u128::from(c - b'0'),
+            _ => u128::from(c - b'A') + 10,
+        };
+
+        let decimal = |bytes: &[u8]| bytes.iter().fold(0u128, |acc, c| acc * 10 + valor(c));
+        let base_36 = |bytes: &[u8]| bytes.iter().fold(0u128, |acc, c| acc * 36 + valor(c));
+
+        let inicio = decimal(&texto[..6]) * BASE_CNPJ + base_36(&texto[6..18]);
+        let fim = decimal(&texto[18..]);
+
+        let mut bytes = [0u8; 22];
+        bytes[..METADE].copy_from_slice(&inicio.to_be_bytes()[16 - METADE..]);
+        bytes[METADE..].copy_from_slice(&fim.to_be_bytes()[16 - METADE..]);
+
+        ChaveAcesso(bytes)
+    }
+
+    /// Lê uma das metades (11 bytes big-endian) como inteiro.
+    fn metade(&self, i: usize) -> u128 {
+        let mut buffer = [0u8; 16];
+        buffer[16 - METADE..].copy_from_slice(&self.0[i * METADE..(i + 1) * METADE]);
+        u128::from_be_bytes(buffer)
+    }
+
+    /// Reconstrói os 44 caracteres ASCII da chave.
+    pub(crate) fn caracteres(&self) -> [u8; 44] {
+        let mut texto = [b'0'; 44];
+
+        let mut escrever = |posicoes: std::ops::Range<usize>, mut valor: u128, base: u128| {
+            for i in posicoes.rev() {
+                let resto = (valor % base) as u8;
+                texto[i] = if resto < 10 {
+                    b'0' + resto
+                } else {
+                    b'A' + resto - 10
+                };
+                valor /= base;
+            }
+        };
+
+        let inicio = self.metade(0);
+        escrever(0..6, inicio / BASE_CNPJ, 10);
+        escrever(6..18, inicio % BASE_CNPJ, 36);
+        escrever(18..44, self.metade(1), 10);
+
+        texto
+    }
+
+    /// Campo numérico contido nos 26 dígitos finais (posições 19..44 da chave).
+    ///
+    /// `inicio` e `fim` são posições (base 0) na chave de 44 posições.
+    fn digitos(&self, inicio: usize, fim: usize) -> u128 {
+        (self.metade(1) / 10u128.pow((44 - fim) as u32)) % 10u128.pow((fim - inicio) as u32)
+    }
+
+    /// Código da UF do emitente (cUF).
+    pub fn cuf(&self) -> u8 {
+        (self.metade(0) / BASE_CNPJ / 10_000) as u8
+    }
+
+    /// Ano e mês de emissão (AAMM), ex: `2401`.
+    pub fn aamm(&self) -> u16 {
+        (self.metade(0) / BASE_CNPJ % 10_000) as u16
+    }
+
+    /// CNPJ (numérico ou alfanumérico) ou CPF do emitente.
+    pub fn emitente(&self) -> String {
+        // 12 posições na base 36 (posições 07..18) seguidas de 2 dígitos (posições 19..20)
+        let mut base_36 = self.metade(0) % BASE_CNPJ;
+        let mut texto = [b'0'; 12];
+        for c in texto.iter_mut().rev() {
+            let resto = (base_36 % 36) as u8;
+            *c = if resto < 10 {
+                b'0' + resto
+            } else {
+                b'A' + resto - 10
+            };
+            base_36 /= 36;
+        }
+
+        // Os caracteres são sempre ASCII (dígitos e letras maiúsculas)
+        format!(
+            "{}{:02}",
+            String::from_utf8_lossy(&texto),
+            self.digitos(18, 20)
+        )
+    }
+
+    /// CPF do emitente pessoa física (`None` se o emitente for um CNPJ).
+    ///
+    /// Na chave, o CPF ocupa as 11 últimas posições do campo do emitente, precedido
+    /// de `000`. Como há CNPJs que também começam por `000`, o CPF só é reconhecido
+    /// quando os seus dígitos verificadores conferem e os do CNPJ não.
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::ChaveAcesso;
+    ///
+    /// let cpf: ChaveAcesso = "35240100012345678909550010000012341123456780".parse().unwrap();
+    /// let cnpj: ChaveAcesso = "35240100000000000191550010000012341123456780".parse().unwrap();
+    ///
+    /// assert_eq!(cpf.cpf_emitente().as_deref(), Some("12345678909"));
+    /// assert_eq!(cnpj.cpf_emitente(), None);
+    /// ```
+    pub fn cpf_emitente(&self) -> Option<String> {
+        let emitente = self.emitente();
+        let cpf = emitente.strip_prefix("000")?;
+
+        (cpf.bytes().all(|c| c.is_ascii_digit())
+            && digitos_verificadores_conferem(cpf.as_bytes(), 11)
+            && !digitos_verificadores_conferem(emitente.as_bytes(), 9))
+        .then(|| cpf.to_string())
+    }
+
+    /// Modelo do documento fiscal (ex: 55 para NFe, 57 para CTe).
+    pub fn modelo(&self) -> Modelo {
+        Modelo(self.digitos(20, 22) as u8)
+    }
+
+    /// Série do documento fiscal.
+    pub fn serie(&self) -> u16 {
+        self.digitos(22, 25) as u16
+    }
+
+    /// Número do documento fiscal.
+    pub fn numero(&self) -> u32 {
+        self.digitos(25, 34) as u32
+    }
+
+    /// Forma de emissão (tpEmis).
+    pub fn tp_emis(&self) -> u8 {
+        self.digitos(34, 35) as u8
+    }
+
+    /// Código numérico que compõe a chave (cNF).
+    pub fn cnf(&self) -> u32 {
+        self.digitos(35, 43) as u32
+    }
+
+    /// Dígito verificador (DV).
+    pub fn dv(&self) -> u8 {
+        self.digitos(43, 44) as u8
+    }
+
+    /// Verifica se o modelo da chave (posições 21-22) coincide.
+    ///
+    /// Consulta direta na forma compacta, sem conversão para texto.
+    pub fn eh_modelo(&self, modelo: &str) -> bool {
+        modelo.len() == 2
+            && modelo
+                .parse::<u8>()
+                .is_ok_and(|m| Modelo(m) == self.modelo())
+    }
+
+    /// Calcula o dígito verificador (módulo 11) a partir das 43 primeiras posições.
+    ///
+    /// Os pesos de 2 a 9 são aplicados da direita para a esquerda, reiniciando em 2.
+    /// Se o resto da divisão por 11 for 0 ou 1, o DV é 0; caso contrário, é 11 - resto.
+    ///
+    /// Cada posição vale o seu código ASCII menos 48: os dígitos mantêm o seu valor
+    /// e as letras do CNPJ alfanumérico valem de 17 ('A') a 42 ('Z').
+    pub fn calcular_dv(&self) -> u8 {
+        let soma: u32 = self.caracteres()[..43]
+            .iter()
+            .rev()
+            .zip((2..=9).cycle())
+            .map(|(&digito, peso)| u32::from(digito - b'0') * peso)
+            .sum();
+
+        match soma % 11 {
+            0 | 1 => 0,
+            resto => (11 - resto) as u8,
+        }
+    }
+
+    /// Verifica se o DV informado na chave coincide com o DV calculado.
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::ChaveAcesso;
+    ///
+    /// let valida: ChaveAcesso = "35240112345678000190550010000012341123456786".parse().unwrap();
+    /// let errada: ChaveAcesso = "35240112345678000190550010000012341123456780".parse().unwrap();
+    ///
+    /// assert!(valida.dv_valido());
+    /// assert!(!errada.dv_valido());
+    /// ```
+    pub fn dv_valido(&self) -> bool {
+        // O DV é o último dos 26 dígitos finais
+        self.dv() == self.calcular_dv()
+    }
+}
+
+/// Confere os dois dígitos verificadores (módulo 11) de um CNPJ ou CPF.
+///
+/// Os pesos vão de 2 a `peso_maximo`, da direita para a esquerda (9 no CNPJ, reiniciando
+/// em 2; 11 no CPF). As letras do CNPJ alfanumérico valem o seu código ASCII menos 48.
+fn digitos_verificadores_conferem(texto: &[u8], peso_maximo: u32) -> bool {
+    let n = texto.len();
+
+    (n - 2..n).all(|i| {
+        let soma: u32 = texto[..i]
+            .iter()
+            .rev()
+            .zip((2..=peso_maximo).cycle())
+            .map(|(&c, peso)| u32::from(c - b'0') * peso)
+            .sum();
+
+        let dv = match soma % 11 {
+            0 | 1 => 0,
+            resto => 11 - resto,
+        };
+
+        u32::from(texto[i] - b'0') == dv
+    })
+}
+
+/// Modelo do documento fiscal contido na chave (posições 21-22).
+///
+/// A conversão para texto (`55`, `57`, ...) ocorre apenas na saída.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::{ChaveAcesso, Modelo};
+///
+/// let chave: ChaveAcesso = "35240112345678000190570010000012341123456786".parse().unwrap();
+///
+/// assert_eq!(chave.modelo(), Modelo(57));
+/// assert_eq!(Modelo(1).to_string(), "01");
+/// assert_eq!(chave.modelo().descricao(), "Conhecimento de Transporte Eletrônico: CT-e");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Modelo(pub u8);
+
+impl Modelo {
+    /// Nome do documento fiscal correspondente ao modelo.
+    pub fn descricao(&self) -> &'static str {
+        get_modelo_documentos_fiscais(&self.to_string())
+    }
+}
+
+impl fmt::Display for Modelo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}", self.0)
+    }
+}
+
+/// Ocorrência de chave de acesso com dígito verificador inválido.
+#[derive(Debug, Clone)]
+pub struct ChaveInvalida {
+    pub chave: ChaveAcesso,
+    pub arquivo: PathBuf,
+    pub linha: usize,
+}
+
+impl FromStr for ChaveAcesso {
+    type Err = SpedError;
+
+    /// Converte uma string de exatamente 44 posições em `ChaveAcesso`.
+    ///
+    /// A limpeza de separadores e a conversão para maiúsculas são responsabilidade de quem chama.
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::ChaveAcesso;
+    ///
+    /// // Dígitos não ASCII (ex: U+0966, zero devanágari) são rejeitados.
+    /// let chave = format!("35240112345678000190{}", "\u{0966}".repeat(24));
+    /// assert!(chave.parse::<ChaveAcesso>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Apenas 44 bytes ASCII: `compactar` opera byte a byte.
+        if !(s.len() == 44 && s.is_ascii() && RE_CHAVE_44.is_match(s)) {
+            return Err(SpedError::InvalidChave {
+                chave: s.to_string(),
+                length: s.chars().count(),
+            });
+        }
+
+        Ok(ChaveAcesso::compactar(s.as_bytes()))
+    }
+}
+
+impl fmt::Display for ChaveAcesso {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.caracteres()))
+    }
+}
+
+impl fmt::Debug for ChaveAcesso {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ChaveAcesso({self})")
+    }
+}
+
+/// Identificação de documento fiscal sem chave de acesso (ex: modelos 01 e 1B).
+///
+/// A correspondência entre EFD e Documentos Fiscais é feita pelo CNPJ/CPF do
+/// participante, pelo número e pelo modelo do documento, todos normalizados:
+/// uma NFe e um CTe do mesmo fornecedor com o mesmo número não se confundem.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::DocumentoSemChave;
+///
+/// let efd = DocumentoSemChave::new("12.abc.345/01de-35", "000777", " 55 ");
+/// let doc = DocumentoSemChave::new("12ABC34501DE35", "777", "55");
+/// let cte = DocumentoSemChave::new("12ABC34501DE35", "777", "57");
+///
+/// assert!(efd.is_some());
+/// assert_eq!(efd, doc);
+/// assert_ne!(efd, cte);
+/// assert_eq!(DocumentoSemChave::new("", "777", "55"), None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DocumentoSemChave {
+    pub cnpj: String,
+    pub numero: String,
+    /// Modelo do documento fiscal informado na EFD (ex: `01`, `1B`, `55`).
+    pub modelo: String,
+}
+
+impl DocumentoSemChave {
+    /// Normaliza CNPJ/CPF (apenas dígitos e letras maiúsculas), número (sem zeros à
+    /// esquerda) e modelo (sem espaços, em maiúsculas).
+    ///
+    /// Retorna `None` se o CNPJ ou o número estiver vazio após a normalização.
+    pub fn new(cnpj: &str, numero: &str, modelo: &str) -> Option<Self> {
+        let cnpj: String = cnpj
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        let numero: String = numero.chars().filter(|c| c.is_ascii_digit()).collect();
+        let numero = numero.trim_start_matches('0');
+
+        if cnpj.is_empty() || numero.is_empty() {
+            return None;
+        }
+
+        Some(DocumentoSemChave {
+            cnpj,
+            numero: numero.to_string(),
+            modelo: modelo.trim().to_ascii_uppercase(),
+        })
+    }
+}
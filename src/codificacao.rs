@@ -0,0 +1,329 @@
+use clap::ValueEnum;
+use encoding_rs::WINDOWS_1252;
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::{SpedError, SpedResult};
+
+/// Tamanho do bloco lido do arquivo original a cada transcodificação.
+const TAMANHO_DO_BLOCO: usize = 64 * 1024;
+
+/// Codificação dos arquivos de entrada (opção `--encoding`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Codificacao {
+    /// Detecção automática: UTF-8 ou, ao primeiro byte inválido, Windows-1252
+    #[default]
+    Auto,
+    /// UTF-8
+    #[value(name = "utf-8", alias = "utf8")]
+    Utf8,
+    /// ISO-8859-1 (Latin-1)
+    #[value(name = "latin1", alias = "iso-8859-1")]
+    Latin1,
+    /// Windows-1252 (superconjunto do Latin-1 usado pelo Windows)
+    #[value(name = "windows-1252", alias = "cp1252")]
+    Windows1252,
+}
+
+impl Codificacao {
+    /// Converte o texto (UTF-8) para os bytes desta codificação.
+    ///
+    /// Caracteres sem representação em Latin-1 são substituídos por `?`;
+    /// em Windows-1252, por referências numéricas (`&#NNNN;`).
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::Codificacao;
+    ///
+    /// assert_eq!(Codificacao::Latin1.codificar("Ação").as_ref(), b"A\xe7\xe3o");
+    /// assert_eq!(Codificacao::Windows1252.codificar("€").as_ref(), b"\x80");
+    /// assert_eq!(Codificacao::Utf8.codificar("Ação").as_ref(), "Ação".as_bytes());
+    /// ```
+    pub fn codificar<'a>(&self, texto: &'a str) -> Cow<'a, [u8]> {
+        match self {
+            Codificacao::Auto | Codificacao::Utf8 => Cow::Borrowed(texto.as_bytes()),
+            Codificacao::Latin1 => Cow::Owned(
+                texto
+                    .chars()
+                    .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+                    .collect(),
+            ),
+            Codificacao::Windows1252 => WINDOWS_1252.encode(texto).0,
+        }
+    }
+}
+
+/// Leitor que transcodifica o conteúdo do arquivo original para UTF-8.
+///
+/// No modo automático, os bytes são repassados enquanto formarem UTF-8 válido;
+/// ao primeiro byte inválido, o restante do arquivo é lido como Windows-1252.
+///
+/// ```
+/// use std::io::Read;
+/// use reter_linhas_com_info_das_chaves::{Codificacao, LeitorDecodificado};
+///
+/// let latin1: &[u8] = b"Descri\xe7\xe3o;Situa\xe7\xe3o";
+/// let mut leitor = LeitorDecodificado::new(latin1, Codificacao::Auto);
+///
+/// let mut texto = String::new();
+/// leitor.read_to_string(&mut texto).unwrap();
+///
+/// assert_eq!(texto, "Descrição;Situação");
+/// assert_eq!(leitor.codificacao(), Codificacao::Windows1252);
+/// ```
+#[derive(Debug)]
+pub struct LeitorDecodificado<R> {
+    interno: R,
+    codificacao: Codificacao,
+    /// Bytes ainda não transcodificados (sequência UTF-8 incompleta no fim do bloco).
+    pendentes: Vec<u8>,
+    /// Conteúdo transcodificado (UTF-8) e posição já entregue.
+    saida: Vec<u8>,
+    posicao: usize,
+    fim: bool,
+}
+
+impl<R: Read> LeitorDecodificado<R> {
+    pub fn new(interno: R, codificacao: Codificacao) -> Self {
+        LeitorDecodificado {
+            interno,
+            codificacao,
+            pendentes: Vec::new(),
+            saida: Vec::new(),
+            posicao: 0,
+            fim: false,
+        }
+    }
+
+    /// Codificação em uso: no modo automático, `Auto` indica que, até aqui, o conteúdo é UTF-8.
+    pub fn codificacao(&self) -> Codificacao {
+        self.codificacao
+    }
+
+    fn preencher(&mut self) -> io::Result<()> {
+        self.saida.clear();
+        self.posicao = 0;
+
+        let inicio = self.pendentes.len();
+        self.pendentes.resize(inicio + TAMANHO_DO_BLOCO, 0);
+        let lidos = self.interno.read(&mut self.pendentes[inicio..])?;
+        self.pendentes.truncate(inicio + lidos);
+        self.fim = lidos == 0;
+
+        let bytes = std::mem::take(&mut self.pendentes);
+
+        match self.codificacao {
+            Codificacao::Utf8 => self.saida = bytes,
+            Codificacao::Latin1 => {
+                let texto: String = bytes.iter().map(|&b| char::from(b)).collect();
+                self.saida = texto.into_bytes();
+            }
+            Codificacao::Windows1252 => {
+                let (texto, _) = WINDOWS_1252.decode_without_bom_handling(&bytes);
+                self.saida = texto.into_owned().into_bytes();
+            }
+            Codificacao::Auto => match std::str::from_utf8(&bytes) {
+                Ok(_) => self.saida = bytes,
+                // Sequência incompleta no fim do bloco: aguarda o próximo bloco
+                Err(e) if e.error_len().is_none() && !self.fim => {
+                    let (validos, resto) = bytes.split_at(e.valid_up_to());
+                    self.saida.extend_from_slice(validos);
+                    self.pendentes = resto.to_vec();
+                }
+                // Byte inválido: o restante do arquivo é lido como Windows-1252
+                Err(e) => {
+                    let (validos, resto) = bytes.split_at(e.valid_up_to());
+                    let (texto, _) = WINDOWS_1252.decode_without_bom_handling(resto);
+                    self.saida.extend_from_slice(validos);
+                    self.saida.extend_from_slice(texto.as_bytes());
+                    self.codificacao = Codificacao::Windows1252;
+                }
+            },
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for LeitorDecodificado<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.posicao >= self.saida.len() {
+            if self.fim && self.pendentes.is_empty() {
+                return Ok(0);
+            }
+            self.preencher()?;
+        }
+
+        let disponiveis = &self.saida[self.posicao..];
+        let n = disponiveis.len().min(buf.len());
+        buf[..n].copy_from_slice(&disponiveis[..n]);
+        self.posicao += n;
+
+        Ok(n)
+    }
+}
+
+/// Decodifica o conteúdo de um arquivo XML para UTF-8.
+///
+/// No modo automático, prevalece a codificação da declaração XML
+/// (`<?xml version="1.0" encoding="ISO-8859-1"?>`); sem declaração reconhecida,
+/// vale a detecção automática de `LeitorDecodificado`. Uma codificação informada
+/// em `--encoding` prevalece sobre a declaração.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::{Codificacao, decodificar_xml};
+///
+/// let latin1: &[u8] = b"<?xml version='1.0' encoding='ISO-8859-1'?><xNome>Jo\xe3o</xNome>";
+/// let texto = decodificar_xml(latin1, Codificacao::Auto).unwrap();
+///
+/// assert!(texto.ends_with("<xNome>João</xNome>"));
+/// assert_eq!(decodificar_xml("<a>Ação</a>".as_bytes(), Codificacao::Auto).unwrap(), "<a>Ação</a>");
+/// ```
+pub fn decodificar_xml(bytes: &[u8], codificacao: Codificacao) -> io::Result<String> {
+    let codificacao = match codificacao {
+        Codificacao::Auto => codificacao_declarada_no_xml(bytes).unwrap_or(Codificacao::Auto),
+        informada => informada,
+    };
+
+    let mut texto = String::with_capacity(bytes.len());
+    LeitorDecodificado::new(bytes, codificacao).read_to_string(&mut texto)?;
+
+    Ok(texto)
+}
+
+/// Codificação do atributo `encoding` da declaração XML, se reconhecida.
+fn codificacao_declarada_no_xml(bytes: &[u8]) -> Option<Codificacao> {
+    // A declaração, se houver, está no início do arquivo e é ASCII
+    let inicio = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let declaracao = inicio.strip_prefix(b"<?xml")?;
+    let fim = declaracao.windows(2).position(|par| par == b"?>")?;
+    let declaracao = std::str::from_utf8(&declaracao[..fim]).ok()?;
+
+    let (_, resto) = declaracao.split_once("encoding")?;
+    let resto = resto.trim_start().strip_prefix('=')?.trim_start();
+    let aspas = resto.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let (nome, _) = resto[1..].split_once(aspas)?;
+
+    match nome.to_ascii_lowercase().as_str() {
+        "utf-8" | "utf8" => Some(Codificacao::Utf8),
+        "iso-8859-1" | "iso8859-1" | "latin1" | "latin-1" => Some(Codificacao::Latin1),
+        "windows-1252" | "cp1252" => Some(Codificacao::Windows1252),
+        _ => None,
+    }
+}
+
+/// Abre o arquivo para leitura, transcodificando o conteúdo para UTF-8.
+pub fn abrir_decodificado(
+    path: &Path,
+    codificacao: Codificacao,
+) -> SpedResult<LeitorDecodificado<File>> {
+    let file = File::open(path).map_err(|e| SpedError::IoReader {
+        source: e,
+        arquivo: path.to_path_buf(),
+    })?;
+
+    Ok(LeitorDecodificado::new(file, codificacao))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lê todo o conteúdo em porções de `tamanho` bytes.
+    fn ler(bytes: &[u8], codificacao: Codificacao, tamanho: usize) -> (String, Codificacao) {
+        let mut leitor = LeitorDecodificado::new(bytes, codificacao);
+        let mut saida = Vec::new();
+        let mut buf = vec![0; tamanho];
+
+        loop {
+            let n = leitor.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            saida.extend_from_slice(&buf[..n]);
+        }
+
+        (String::from_utf8(saida).unwrap(), leitor.codificacao())
+    }
+
+    #[test]
+    fn caractere_utf8_dividido_entre_blocos_nao_muda_a_codificacao() {
+        let texto = format!("{}ção", "a".repeat(TAMANHO_DO_BLOCO - 1));
+
+        let (lido, codificacao) = ler(texto.as_bytes(), Codificacao::Auto, 4096);
+
+        assert_eq!(lido, texto);
+        assert_eq!(codificacao, Codificacao::Auto);
+    }
+
+    #[test]
+    fn byte_invalido_apos_o_primeiro_bloco_muda_para_windows_1252() {
+        let prefixo = "ç".repeat(TAMANHO_DO_BLOCO);
+        let mut bytes = prefixo.as_bytes().to_vec();
+        bytes.extend_from_slice(b";Situa\xe7\xe3o \x80");
+
+        let (lido, codificacao) = ler(&bytes, Codificacao::Auto, 1000);
+
+        assert_eq!(lido, format!("{prefixo};Situação €"));
+        assert_eq!(codificacao, Codificacao::Windows1252);
+    }
+
+    #[test]
+    fn sequencia_incompleta_no_fim_do_arquivo_e_lida_como_windows_1252() {
+        let (lido, codificacao) = ler(b"abc\xc3", Codificacao::Auto, 1);
+
+        assert_eq!(lido, "abcÃ");
+        assert_eq!(codificacao, Codificacao::Windows1252);
+    }
+
+    #[test]
+    fn latin1_e_windows_1252_diferem_nos_bytes_0x80_a_0x9f() {
+        let bytes = b"\x80\x93\xe9";
+
+        assert_eq!(ler(bytes, Codificacao::Latin1, 2).0, "\u{80}\u{93}é");
+        assert_eq!(ler(bytes, Codificacao::Windows1252, 2).0, "€“é");
+    }
+
+    #[test]
+    fn leitura_em_porcoes_de_um_byte_preserva_o_conteudo() {
+        let texto = "Descrição;Situação;Observações\n".repeat(100);
+
+        assert_eq!(ler(texto.as_bytes(), Codificacao::Utf8, 1).0, texto);
+        assert_eq!(ler(b"", Codificacao::Auto, 1).0, "");
+    }
+
+    #[test]
+    fn declaracao_xml_reconhecida() {
+        let declarada = |xml: &[u8]| codificacao_declarada_no_xml(xml);
+
+        assert_eq!(
+            declarada(b"\xEF\xBB\xBF<?xml version=\"1.0\" encoding = \"UTF-8\"?><a/>"),
+            Some(Codificacao::Utf8)
+        );
+        assert_eq!(
+            declarada(b"<?xml version='1.0' encoding='windows-1252'?><a/>"),
+            Some(Codificacao::Windows1252)
+        );
+        assert_eq!(declarada(b"<?xml version='1.0' encoding='UTF-16'?>"), None);
+        assert_eq!(declarada(b"<?xml version='1.0'?><a/>"), None);
+        assert_eq!(declarada(b"<a encoding='latin1'/>"), None);
+    }
+
+    #[test]
+    fn codificacao_informada_prevalece_sobre_a_declaracao() {
+        let xml = "<?xml version='1.0' encoding='ISO-8859-1'?><a>Ação</a>";
+
+        assert_eq!(
+            decodificar_xml(xml.as_bytes(), Codificacao::Utf8).unwrap(),
+            xml
+        );
+        assert!(
+            decodificar_xml(xml.as_bytes(), Codificacao::Auto)
+                .unwrap()
+                .ends_with("<a>AÃ§Ã£o</a>")
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::BufWriter,
+    ops::{AddAssign, Sub},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{ChaveAcesso, EscrituracaoEfd, InfoDocs, InfoEfd, SpedError, SpedResult, fmt_milhares};
+
+/// Valor monetário em centavos.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Valor(pub i64);
+
+impl Valor {
+    /// Converte valores no formato brasileiro (`1.234,56`) ou com ponto decimal (`1234.56`).
+    ///
+    /// Sem vírgula, o ponto é separador de milhares quando todos os grupos seguintes têm
+    /// 3 dígitos (`1.234` é `1.234,00`, como na EFD e nos relatórios da ReceitaNet-BX);
+    /// caso contrário, é o separador decimal. A conversão é feita em inteiros (centavos),
+    /// arredondando-se a partir da terceira casa decimal.
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::Valor;
+    ///
+    /// assert_eq!(Valor::parse("1.234,56"), Some(Valor(123456)));
+    /// assert_eq!(Valor::parse("1.234"), Some(Valor(123400)));
+    /// assert_eq!(Valor::parse("1.234.567"), Some(Valor(123456700)));
+    /// assert_eq!(Valor::parse("7,6"), Some(Valor(760)));
+    /// assert_eq!(Valor::parse("0.05"), Some(Valor(5)));
+    /// assert_eq!(Valor::parse("-0,125"), Some(Valor(-13)));
+    /// assert_eq!(Valor::parse(""), None);
+    /// assert_eq!(Valor::parse("1,2,3"), None);
+    /// assert_eq!(Valor::parse("1.23.4"), None);
+    /// assert_eq!(Valor(-123456).to_string(), "-1.234,56");
+    /// ```
+    pub fn parse(texto: &str) -> Option<Valor> {
+        let texto = texto.trim();
+        let (negativo, texto) = match texto.strip_prefix('-') {
+            Some(resto) => (true, resto),
+            None => (false, texto.strip_prefix('+').unwrap_or(texto)),
+        };
+
+        let (inteiro, fracao) = match texto.split_once(',') {
+            Some((inteiro, fracao)) => (inteiro, fracao),
+            None => {
+                let grupos: Vec<&str> = texto.split('.').collect();
+                match grupos[..] {
+                    [inteiro, fracao] if fracao.len() != 3 => (inteiro, fracao),
+                    [_, ref milhares @ ..] if milhares.iter().all(|g| g.len() == 3) => (texto, ""),
+                    _ => return None,
+                }
+            }
+        };
+
+        let inteiro = inteiro.replace('.', "");
+        let digitos = |parte: &str| parte.bytes().all(|b| b.is_ascii_digit());
+        if (inteiro.is_empty() && fracao.is_empty()) || !digitos(&inteiro) || !digitos(fracao) {
+            return None;
+        }
+
+        let reais: i64 = if inteiro.is_empty() {
+            0
+        } else {
+            inteiro.parse().ok()?
+        };
+        let casa = |i: usize| i64::from(fracao.as_bytes().get(i).map_or(0, |b| b - b'0'));
+        let centavos = casa(0) * 10 + casa(1) + i64::from(casa(2) >= 5);
+
+        let valor = reais.checked_mul(100)?.checked_add(centavos)?;
+        Some(Valor(if negativo { -valor } else { valor }))
+    }
+
+    pub fn abs(self) -> Valor {
+        Valor(self.0.abs())
+    }
+}
+
+impl FromStr for Valor {
+    type Err = SpedError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Valor::parse(s).ok_or_else(|| SpedError::ValorInvalido {
+            valor: s.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Valor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sinal = if self.0 < 0 { "-" } else { "" };
+        let centavos = self.0.unsigned_abs();
+        write!(
+            f,
+            "{sinal}{},{:02}",
+            fmt_milhares((centavos / 100) as usize),
+            centavos % 100
+        )
+    }
+}
+
+impl AddAssign for Valor {
+    fn add_assign(&mut self, outro: Valor) {
+        self.0 += outro.0;
+    }
+}
+
+impl Sub for Valor {
+    type Output = Valor;
+
+    fn sub(self, outro: Valor) -> Valor {
+        Valor(self.0 - outro.0)
+    }
+}
+
+/// Valor comparado entre a EFD e os Documentos Fiscais.
+#[derive(Debug, Clone, Copy)]
+pub struct ValorConciliado {
+    /// Nome do valor nos relatórios.
+    pub nome: &'static str,
+    /// Campo da EFD (chave de `COLUNAS_EFD` ou de `COLUNAS_EFD_ICMS_IPI`).
+    pub campo_efd: &'static str,
+    /// Campo dos Documentos Fiscais (chave de `COLUNAS_DOC`); `None` se o valor consta apenas da EFD.
+    pub campo_doc: Option<&'static str>,
+}
+
+/// Valores conciliados da EFD Contribuições.
+pub const VALORES_CONCILIADOS_CONTRIBUICOES: [ValorConciliado; 5] = [
+    ValorConciliado {
+        nome: "Valor dos Itens",
+        campo_efd: "valor_do_item",
+        campo_doc: Some("valor_proporcional"),
+    },
+    ValorConciliado {
+        nome: "Base de Cálculo das Contribuições",
+        campo_efd: "valor_bc_contrib",
+        campo_doc: None,
+    },
+    ValorConciliado {
+        nome: "PIS/PASEP",
+        campo_efd: "valor_de_pis",
+        campo_doc: Some("valor_tributo_pis"),
+    },
+    ValorConciliado {
+        nome: "COFINS",
+        campo_efd: "valor_de_cofins",
+        campo_doc: Some("valor_tributo_cofins"),
+    },
+    ValorConciliado {
+        nome: "ICMS",
+        campo_efd: "valor_de_icms",
+        campo_doc: Some("valor_icms"),
+    },
+];
+
+/// Valores conciliados da EFD ICMS/IPI.
+pub const VALORES_CONCILIADOS_ICMS_IPI: [ValorConciliado; 5] = [
+    ValorConciliado {
+        nome: "Valor dos Itens",
+        campo_efd: "valor_do_item",
+        campo_doc: Some("valor_proporcional"),
+    },
+    ValorConciliado {
+        nome: "Base de Cálculo do ICMS",
+        campo_efd: "valor_bc_icms",
+        campo_doc: Some("valor_bc_icms"),
+    },
+    ValorConciliado {
+        nome: "ICMS",
+        campo_efd: "valor_de_icms",
+        campo_doc: Some("valor_icms"),
+    },
+    ValorConciliado {
+        nome: "ICMS ST",
+        campo_efd: "valor_de_icms_st",
+        campo_doc: Some("valor_icms_sub"),
+    },
+    ValorConciliado {
+        nome: "IPI",
+        campo_efd: "valor_de_ipi",
+        campo_doc: Some("valor_tributo_ipi"),
+    },
+];
+
+/// Opções da conciliação de valores (opção `--conciliar`).
+#[derive(Debug, Clone, Copy)]
+pub struct Conciliacao {
+    /// Conciliar por item (chave e número do item), em vez de por chave.
+    pub por_item: bool,
+    /// Diferença máxima aceita entre os valores da EFD e dos Documentos Fiscais.
+    pub tolerancia: Valor,
+}
+
+/// Documento (chave) e número do item, quando informado.
+pub type ItemConciliado = (ChaveAcesso, Option<u32>);
+
+/// Número do item (`None` se ausente ou não numérico).
+pub fn numero_do_item(texto: &str) -> Option<u32> {
+    texto.trim().parse().ok()
+}
+
+/// Acumula os valores de uma linha (ou de um resultado parcial) dos Documentos Fiscais.
+///
+/// Cada item numerado é considerado uma única vez, ainda que repetido em arquivos
+/// distintos. Sem o número do item não há como identificar repetições: os valores
+/// são somados, para que o total da chave não se limite à primeira linha.
+pub fn acumular_item(
+    valores: &mut HashMap<ItemConciliado, ValoresDoItem>,
+    item: ItemConciliado,
+    novos: ValoresDoItem,
+) {
+    match item {
+        (_, Some(_)) => {
+            valores.entry(item).or_insert(novos);
+        }
+        (_, None) => valores.entry(item).or_default().somar(&novos),
+    }
+}
+
+/// Valores de um documento (ou item), na ordem de `EscrituracaoEfd::valores_conciliados`.
+#[derive(Debug, Clone, Default)]
+pub struct ValoresDoItem {
+    /// Número de linhas somadas.
+    pub linhas: usize,
+    pub valores: Vec<Valor>,
+}
+
+impl ValoresDoItem {
+    /// Valores de uma linha (campos vazios ou inválidos são considerados zero).
+    pub fn from_campos<'a>(campos: impl IntoIterator<Item = &'a str>) -> Self {
+        ValoresDoItem {
+            linhas: 1,
+            valores: campos
+                .into_iter()
+                .map(|campo| Valor::parse(campo).unwrap_or_default())
+                .collect(),
+        }
+    }
+
+    /// Acumula os valores de outra linha (ou item).
+    pub fn somar(&mut self, outro: &ValoresDoItem) {
+        if self.valores.len() < outro.valores.len() {
+            self.valores.resize(outro.valores.len(), Valor::default());
+        }
+        for (valor, parcela) in self.valores.iter_mut().zip(&outro.valores) {
+            *valor += *parcela;
+        }
+        self.linhas += outro.linhas;
+    }
+}
+
+/// Imprime o resumo e exporta a conciliação de valores em `<target>-Conciliação de Valores.csv`.
+pub fn exportar_conciliacao_de_valores(
+    info_efd: &InfoEfd,
+    info_docs: &InfoDocs,
+    escrituracao: EscrituracaoEfd,
+    conciliacao: Conciliacao,
+    target_base: &Path,
+) -> SpedResult<()> {
+    let valores_conciliados = escrituracao.valores_conciliados();
+
+    // Sem `--conciliar-itens`, os itens são somados por chave
+    let agrupar = |&(chave, item): &ItemConciliado| -> ItemConciliado {
+        (chave, if conciliacao.por_item { item } else { None })
+    };
+
+    let encontradas: HashSet<ChaveAcesso> =
+        info_docs.valores.keys().map(|&(chave, _)| chave).collect();
+
+    let mut linhas: BTreeMap<ItemConciliado, (Option<ValoresDoItem>, Option<ValoresDoItem>)> =
+        BTreeMap::new();
+
+    for (item, valores) in &info_efd.valores {
+        if encontradas.contains(&item.0) {
+            let (efd, _) = linhas.entry(agrupar(item)).or_default();
+            efd.get_or_insert_default().somar(valores);
+        }
+    }
+
+    for (item, valores) in &info_docs.valores {
+        let (_, doc) = linhas.entry(agrupar(item)).or_default();
+        doc.get_or_insert_default().somar(valores);
+    }
+
+    println!(
+        " Conciliação de valores entre a {} e os Documentos Fiscais (tolerância: {}):\n",
+        escrituracao.nome(),
+        conciliacao.tolerancia
+    );
+
+    if linhas.is_empty() {
+        println!(" Nenhuma chave da EFD encontrada nos Documentos Fiscais para conciliação.\n");
+        return Ok(());
+    }
+
+    let file_path = format!("{}-Conciliação de Valores.csv", target_base.display());
+
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(BufWriter::new(File::create(&file_path)?));
+
+    let mut headers = vec!["Chave".to_string(), "Modelo".to_string()];
+    if conciliacao.por_item {
+        headers.push("Nº do Item".to_string());
+    }
+    headers.push("Linhas da EFD".to_string());
+    headers.push("Valor Total da NF (Documentos)".to_string());
+    for valor in valores_conciliados {
+        headers.push(format!("{} (EFD)", valor.nome));
+        if valor.campo_doc.is_some() {
+            headers.push(format!("{} (Documentos)", valor.nome));
+            headers.push(format!("Diferença de {}", valor.nome));
+        }
+    }
+    headers.push("Situação".to_string());
+    wtr.write_record(&headers)?;
+
+    let vazio = ValoresDoItem::default();
+    let mut chaves_divergentes: HashSet<ChaveAcesso> = HashSet::new();
+    let mut divergencias_por_valor: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut itens_ausentes = 0;
+
+    for ((chave, item), (efd, doc)) in &linhas {
+        let valores_efd = efd.as_ref().unwrap_or(&vazio);
+        let valores_doc = doc.as_ref().unwrap_or(&vazio);
+        let valor = |valores: &ValoresDoItem, i: usize| valores.valores.get(i).copied();
+
+        let mut record = vec![chave.to_string(), chave.modelo().to_string()];
+        if conciliacao.por_item {
+            record.push(item.map(|n| n.to_string()).unwrap_or_default());
+        }
+        record.push(valores_efd.linhas.to_string());
+        record.push(
+            info_docs
+                .totais_das_notas
+                .get(chave)
+                .map(Valor::to_string)
+                .unwrap_or_default(),
+        );
+
+        let mut divergentes = Vec::new();
+
+        for (i, conciliado) in valores_conciliados.iter().enumerate() {
+            let efd_valor = valor(valores_efd, i).unwrap_or_default();
+            record.push(efd_valor.to_string());
+
+            if conciliado.campo_doc.is_some() {
+                let doc_valor = valor(valores_doc, i).unwrap_or_default();
+                let diferenca = efd_valor - doc_valor;
+                record.push(doc_valor.to_string());
+                record.push(diferenca.to_string());
+
+                if diferenca.abs() > conciliacao.tolerancia {
+                    divergentes.push(conciliado.nome);
+                    *divergencias_por_valor.entry(i).or_default() += 1;
+                }
+            }
+        }
+
+        let situacao = match (efd, doc) {
+            (Some(_), None) => "Item ausente nos Documentos Fiscais".to_string(),
+            (None, Some(_)) => "Item ausente na EFD".to_string(),
+            _ if divergentes.is_empty() => "Conciliado".to_string(),
+            _ => format!("Divergente: {}", divergentes.join(", ")),
+        };
+
+        if efd.is_none() || doc.is_none() {
+            itens_ausentes += 1;
+        }
+        if situacao != "Conciliado" {
+            chaves_divergentes.insert(*chave);
+        }
+
+        record.push(situacao);
+        wtr.write_record(&record)?;
+    }
+
+    wtr.flush()?;
+
+    let total_de_chaves = linhas
+        .keys()
+        .map(|&(chave, _)| chave)
+        .collect::<HashSet<_>>()
+        .len();
+
+    println!(
+        " Chaves conciliadas: {} ; com divergência de valores: {}",
+        fmt_milhares(total_de_chaves - chaves_divergentes.len()),
+        fmt_milhares(chaves_divergentes.len())
+    );
+
+    for (i, num) in divergencias_por_valor {
+        println!(
+            "  {:>9} : {}",
+            fmt_milhares(num),
+            valores_conciliados[i].nome
+        );
+    }
+    if itens_ausentes > 0 {
+        println!(
+            "  {:>9} : Itens presentes em apenas uma das fontes",
+            fmt_milhares(itens_ausentes)
+        );
+    }
+
+    println!(
+        "\n ---> Novo arquivo de conciliação de valores: <{}>\n",
+        file_path
+    );
+
+    Ok(())
+}
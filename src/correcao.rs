@@ -0,0 +1,193 @@
+use rayon::prelude::*;
+use std::{collections::HashSet, fmt, fs::File, io::BufWriter, path::Path};
+
+use crate::{ChaveAcesso, SpedResult, fmt_milhares};
+
+/// Tipo de erro de digitação que separa a chave da EFD da chave candidata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TipoDeErro {
+    /// Um único dígito divergente (distância de Hamming 1). Posição a partir de 1.
+    DigitoDivergente { posicao: usize },
+    /// Dois dígitos adjacentes trocados entre si. Posição do primeiro, a partir de 1.
+    Transposicao { posicao: usize },
+}
+
+impl fmt::Display for TipoDeErro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TipoDeErro::DigitoDivergente { posicao } => {
+                write!(f, "Dígito divergente na posição {posicao}")
+            }
+            TipoDeErro::Transposicao { posicao } => {
+                write!(f, "Transposição das posições {} e {}", posicao, posicao + 1)
+            }
+        }
+    }
+}
+
+/// Correção provável de uma chave da EFD não encontrada nos Documentos Fiscais.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CorrecaoProvavel {
+    pub chave_efd: ChaveAcesso,
+    pub candidata: ChaveAcesso,
+    pub tipo: TipoDeErro,
+}
+
+/// Gera todas as chaves vizinhas: distância de Hamming 1 ou uma transposição adjacente.
+///
+/// As candidatas são geradas diretamente sobre os caracteres da chave e apenas no
+/// formato válido: letras somente nas posições 07 a 18 (CNPJ alfanumérico) e dígitos
+/// nas demais. Assim, cada candidata é compactada sem nova validação do texto.
+fn chaves_vizinhas(chave: &ChaveAcesso) -> Vec<(ChaveAcesso, TipoDeErro)> {
+    const DIGITOS: &[u8] = b"0123456789";
+    const LETRAS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+    let admite_letra = |i: usize| (6..18).contains(&i);
+    let original = chave.caracteres();
+    let mut bytes = original;
+    let mut vizinhas = Vec::with_capacity(44 * 9 + 12 * 26 + 43);
+
+    // 1. Substituição de um único caractere
+    for i in 0..bytes.len() {
+        let letras = if admite_letra(i) { LETRAS } else { &[] };
+        for &caractere in DIGITOS.iter().chain(letras) {
+            if caractere != original[i] {
+                bytes[i] = caractere;
+                vizinhas.push((
+                    ChaveAcesso::compactar(&bytes),
+                    TipoDeErro::DigitoDivergente { posicao: i + 1 },
+                ));
+            }
+        }
+        bytes[i] = original[i];
+    }
+
+    // 2. Troca de dois caracteres adjacentes (diferentes entre si)
+    // Uma letra não pode sair das posições do CNPJ alfanumérico.
+    for i in 0..bytes.len() - 1 {
+        let mesmo_formato = admite_letra(i) == admite_letra(i + 1)
+            || (original[i].is_ascii_digit() && original[i + 1].is_ascii_digit());
+
+        if original[i] != original[i + 1] && mesmo_formato {
+            bytes.swap(i, i + 1);
+            vizinhas.push((
+                ChaveAcesso::compactar(&bytes),
+                TipoDeErro::Transposicao { posicao: i + 1 },
+            ));
+            bytes.swap(i, i + 1);
+        }
+    }
+
+    vizinhas
+}
+
+/// Procura, entre as chaves dos Documentos Fiscais, correções prováveis das chaves
+/// da EFD não encontradas.
+///
+/// Para cada chave da EFD são consideradas candidatas as chaves dos Documentos Fiscais
+/// com um único dígito divergente ou com dois dígitos adjacentes trocados.
+/// Se houver candidatas com DV válido, apenas estas são retidas.
+///
+/// ### Exemplo
+/// ```
+/// use reter_linhas_com_info_das_chaves::{buscar_correcoes_provaveis, ChaveAcesso, TipoDeErro};
+/// use std::collections::HashSet;
+///
+/// let correta: ChaveAcesso = "35240112345678000190550010000012341123456786".parse().unwrap();
+/// let digitada: ChaveAcesso = "35240112345678000190550010000021341123456786".parse().unwrap();
+///
+/// let nao_encontradas = HashSet::from([digitada]);
+/// let docs = HashSet::from([correta]);
+///
+/// let correcoes = buscar_correcoes_provaveis(&nao_encontradas, &docs);
+///
+/// assert_eq!(correcoes.len(), 1);
+/// assert_eq!(correcoes[0].candidata, correta);
+/// assert_eq!(correcoes[0].tipo, TipoDeErro::Transposicao { posicao: 31 });
+/// ```
+pub fn buscar_correcoes_provaveis(
+    nao_encontradas: &HashSet<ChaveAcesso>,
+    chaves_docs: &HashSet<ChaveAcesso>,
+) -> Vec<CorrecaoProvavel> {
+    let mut correcoes: Vec<CorrecaoProvavel> = nao_encontradas
+        .par_iter()
+        .flat_map_iter(|chave_efd| {
+            let candidatas: Vec<CorrecaoProvavel> = chaves_vizinhas(chave_efd)
+                .into_iter()
+                .filter(|(vizinha, _)| chaves_docs.contains(vizinha))
+                .map(|(candidata, tipo)| CorrecaoProvavel {
+                    chave_efd: *chave_efd,
+                    candidata,
+                    tipo,
+                })
+                .collect();
+
+            // Preferência pelas candidatas que passam na verificação do DV
+            if candidatas.iter().any(|c| c.candidata.dv_valido()) {
+                candidatas
+                    .into_iter()
+                    .filter(|c| c.candidata.dv_valido())
+                    .collect()
+            } else {
+                candidatas
+            }
+        })
+        .collect();
+
+    correcoes.sort_unstable();
+    correcoes
+}
+
+/// Imprime o resumo e exporta as correções prováveis em `<target>-Correções Prováveis.csv`.
+pub fn exportar_correcoes_provaveis(
+    correcoes: &[CorrecaoProvavel],
+    target_base: &Path,
+) -> SpedResult<()> {
+    let num_chaves = correcoes
+        .iter()
+        .map(|c| c.chave_efd)
+        .collect::<HashSet<_>>()
+        .len();
+
+    println!(
+        " Número de chaves não encontradas com correção provável nos Documentos Fiscais: {}\n",
+        fmt_milhares(num_chaves)
+    );
+
+    if correcoes.is_empty() {
+        return Ok(());
+    }
+
+    let file_path = format!("{}-Correções Prováveis.csv", target_base.display());
+
+    println!(" ---> Novo arquivo de correções prováveis: <{}>", file_path);
+
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(BufWriter::new(File::create(&file_path)?));
+
+    wtr.write_record([
+        "Chave EFD",
+        "Chave Candidata",
+        "Tipo de Erro",
+        "DV da Candidata",
+    ])?;
+
+    for correcao in correcoes {
+        let dv = if correcao.candidata.dv_valido() {
+            "Válido"
+        } else {
+            "Inválido"
+        };
+
+        wtr.write_record([
+            &correcao.chave_efd.to_string(),
+            &correcao.candidata.to_string(),
+            &correcao.tipo.to_string(),
+            dv,
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
@@ -44,17 +44,57 @@ pub enum SpedError {
     )]
     EfdFileNotFound,
 
+    #[error("Nenhum arquivo da EFD corresponde ao padrão <{padrao}>")]
+    EfdPatternNoMatch { padrao: String },
+
+    #[error(
+        "Arquivo <{arquivo}>: a conversão do arquivo SPED (.txt) está disponível apenas para a EFD Contribuições"
+    )]
+    SpedTxtNaoSuportado { arquivo: PathBuf },
+
     #[error("Arquivo <{arquivo}> contém colunas com nome em branco!")]
     EmptyColumnName { arquivo: PathBuf },
 
-    #[error("CNPJ inválido: {cnpj}. Esperado 14 dígitos, encontrado {length}")]
+    #[error("Filtro {opcao} indisponível na {escrituracao}: coluna <{campo}> ausente no layout")]
+    FiltroIndisponivel {
+        opcao: &'static str,
+        campo: &'static str,
+        escrituracao: &'static str,
+    },
+
+    #[error(
+        "Filtro {opcao} indisponível para o arquivo SPED (.txt) <{arquivo}>: coluna <{campo}> não preenchida na conversão"
+    )]
+    FiltroIndisponivelNoSpedTxt {
+        opcao: &'static str,
+        campo: &'static str,
+        arquivo: PathBuf,
+    },
+
+    #[error("Filtro inválido: <{valor}>. Esperado {esperado}")]
+    FiltroInvalido {
+        valor: String,
+        esperado: &'static str,
+    },
+
+    #[error(
+        "Chave de acesso inválida: {chave}. Esperado 44 posições (dígitos ou CNPJ alfanumérico), encontrado {length}"
+    )]
+    InvalidChave { chave: String, length: usize },
+
+    #[error(
+        "CNPJ inválido: {cnpj}. Esperado 14 posições (dígitos ou CNPJ alfanumérico), encontrado {length}"
+    )]
     InvalidCnpj { cnpj: String, length: usize },
 
+    #[error("Valor inválido: <{valor}>. Esperado valor numérico (ex: 0,05)")]
+    ValorInvalido { valor: String },
+
     #[error("Erro de I/O: {0}")]
     Io(#[from] io::Error),
 
     #[error(
-        "Arquivo EFD não encontrado!\n\
+        "Não foi possível abrir o arquivo!\n\
         Arquivo: {arquivo:?}\n\
         {source}"
     )]
@@ -79,6 +119,23 @@ pub enum SpedError {
 
     #[error("Regex Error: {0}")]
     Regex(#[from] regex::Error),
+
+    #[error("Erro no arquivo XML <{arquivo}>: {source}")]
+    Xml {
+        #[source]
+        source: roxmltree::Error,
+        arquivo: PathBuf,
+    },
+
+    #[error("Arquivo XML <{arquivo}> excede o limite de {limite} bytes")]
+    XmlMuitoGrande { arquivo: PathBuf, limite: u64 },
+
+    #[error("Erro no arquivo ZIP <{arquivo}>: {source}")]
+    Zip {
+        #[source]
+        source: zip::result::ZipError,
+        arquivo: PathBuf,
+    },
 }
 
 impl SpedError {
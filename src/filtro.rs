@@ -0,0 +1,370 @@
+use csv::StringRecord;
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use crate::{AnoMes, RE_NON_ALPHANUMERIC, SpedError, SpedResult, fmt_milhares};
+
+/// Faixa de códigos numéricos (ex: CST `50-66`, CFOP `1000-1999` ou um único código `1102`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaixaDeCodigos {
+    pub inicio: u32,
+    pub fim: u32,
+}
+
+impl FaixaDeCodigos {
+    /// Verifica se o código pertence à faixa.
+    pub fn contem(&self, codigo: u32) -> bool {
+        (self.inicio..=self.fim).contains(&codigo)
+    }
+}
+
+impl FromStr for FaixaDeCodigos {
+    type Err = SpedError;
+
+    /// Converte um código (`50`) ou uma faixa de códigos (`50-66`).
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::FaixaDeCodigos;
+    ///
+    /// let faixa: FaixaDeCodigos = "50-66".parse().unwrap();
+    /// assert!(faixa.contem(50) && faixa.contem(66) && !faixa.contem(70));
+    /// assert_eq!("1102".parse::<FaixaDeCodigos>().unwrap().fim, 1102);
+    /// assert!("66-50".parse::<FaixaDeCodigos>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalido = || SpedError::FiltroInvalido {
+            valor: s.to_string(),
+            esperado: "código ou faixa de códigos (ex: 50 ou 50-66)",
+        };
+
+        let (inicio, fim) = s.split_once('-').unwrap_or((s, s));
+        let inicio: u32 = inicio.trim().parse().map_err(|_| invalido())?;
+        let fim: u32 = fim.trim().parse().map_err(|_| invalido())?;
+
+        if inicio > fim {
+            return Err(invalido());
+        }
+
+        Ok(FaixaDeCodigos { inicio, fim })
+    }
+}
+
+impl fmt::Display for FaixaDeCodigos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.inicio == self.fim {
+            write!(f, "{}", self.inicio)
+        } else {
+            write!(f, "{}-{}", self.inicio, self.fim)
+        }
+    }
+}
+
+/// Intervalo de períodos de apuração (opção `--periodo`), com limites opcionais.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntervaloDePeriodos {
+    pub inicio: Option<AnoMes>,
+    pub fim: Option<AnoMes>,
+}
+
+impl IntervaloDePeriodos {
+    /// Verifica se o período de apuração (ex: `01/2024`) pertence ao intervalo.
+    pub fn contem(&self, periodo_apuracao: &str) -> bool {
+        AnoMes::parse(periodo_apuracao).is_some_and(|periodo| {
+            self.inicio.is_none_or(|inicio| periodo >= inicio)
+                && self.fim.is_none_or(|fim| periodo <= fim)
+        })
+    }
+}
+
+impl FromStr for IntervaloDePeriodos {
+    type Err = SpedError;
+
+    /// Converte `INICIO:FIM` (limites opcionais) ou um único período.
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::IntervaloDePeriodos;
+    ///
+    /// let intervalo: IntervaloDePeriodos = "01/2024:06/2024".parse().unwrap();
+    /// assert!(intervalo.contem("03/2024") && !intervalo.contem("07/2024"));
+    ///
+    /// let a_partir: IntervaloDePeriodos = "2024-07:".parse().unwrap();
+    /// assert!(a_partir.contem("01/2025") && !a_partir.contem("06/2024"));
+    ///
+    /// assert!("06/2024:01/2024".parse::<IntervaloDePeriodos>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalido = || SpedError::FiltroInvalido {
+            valor: s.to_string(),
+            esperado: "período ou intervalo de períodos (ex: 01/2024:06/2024)",
+        };
+
+        let limite = |texto: &str| -> Result<Option<AnoMes>, SpedError> {
+            match texto.trim() {
+                "" => Ok(None),
+                texto => AnoMes::parse(texto).map(Some).ok_or_else(invalido),
+            }
+        };
+
+        let (inicio, fim) = match s.split_once(':') {
+            Some((inicio, fim)) => (limite(inicio)?, limite(fim)?),
+            None => (limite(s)?, limite(s)?),
+        };
+
+        match (inicio, fim) {
+            (None, None) => Err(invalido()),
+            (Some(inicio), Some(fim)) if inicio > fim => Err(invalido()),
+            _ => Ok(IntervaloDePeriodos { inicio, fim }),
+        }
+    }
+}
+
+impl fmt::Display for IntervaloDePeriodos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.inicio, self.fim) {
+            (Some(inicio), Some(fim)) if inicio == fim => write!(f, "{inicio}"),
+            (Some(inicio), Some(fim)) => write!(f, "{inicio} a {fim}"),
+            (Some(inicio), None) => write!(f, "a partir de {inicio}"),
+            (None, Some(fim)) => write!(f, "até {fim}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Filtro das linhas da EFD, na ordem de aplicação.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FiltroEfd {
+    TipoDeCredito,
+    Cst,
+    NaturezaBc,
+    Cfop,
+    Periodo,
+    Estabelecimento,
+}
+
+impl FiltroEfd {
+    /// Campo (chave de `COLUNAS_EFD`) ao qual o filtro se aplica.
+    pub fn campo(&self) -> &'static str {
+        match self {
+            FiltroEfd::TipoDeCredito => "tipo_de_credito",
+            FiltroEfd::Cst => "codigo_cst",
+            FiltroEfd::NaturezaBc => "natureza_bc",
+            FiltroEfd::Cfop => "codigo_cfop",
+            FiltroEfd::Periodo => "periodo_apuracao",
+            FiltroEfd::Estabelecimento => "cnpj_contribuinte",
+        }
+    }
+
+    /// Indica se o campo é preenchido na conversão do arquivo SPED `.txt` (`ConversorEfd`).
+    ///
+    /// O tipo de crédito não consta dos registros de documentos do SPED, e a natureza
+    /// da base de cálculo consta apenas dos registros D101/D105 (não dos itens C170
+    /// nem dos registros A100, C100 e C500).
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::FiltroEfd;
+    ///
+    /// assert!(FiltroEfd::Cst.disponivel_no_sped_txt());
+    /// assert!(!FiltroEfd::TipoDeCredito.disponivel_no_sped_txt());
+    /// ```
+    pub fn disponivel_no_sped_txt(&self) -> bool {
+        !matches!(self, FiltroEfd::TipoDeCredito | FiltroEfd::NaturezaBc)
+    }
+
+    /// Opção da linha de comando correspondente ao filtro.
+    pub fn opcao(&self) -> &'static str {
+        match self {
+            FiltroEfd::TipoDeCredito => "--tipo-de-credito",
+            FiltroEfd::Cst => "--cst",
+            FiltroEfd::NaturezaBc => "--natureza-bc",
+            FiltroEfd::Cfop => "--cfop",
+            FiltroEfd::Periodo => "--periodo",
+            FiltroEfd::Estabelecimento => "--estabelecimento",
+        }
+    }
+}
+
+impl fmt::Display for FiltroEfd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let descricao = match self {
+            FiltroEfd::TipoDeCredito => "Tipo de Crédito",
+            FiltroEfd::Cst => "CST",
+            FiltroEfd::NaturezaBc => "Natureza da Base de Cálculo",
+            FiltroEfd::Cfop => "CFOP",
+            FiltroEfd::Periodo => "Período de Apuração",
+            FiltroEfd::Estabelecimento => "CNPJ do Estabelecimento",
+        };
+        f.write_str(descricao)
+    }
+}
+
+/// Filtros aplicados às linhas da EFD antes da extração das chaves.
+///
+/// Filtros vazios aceitam todas as linhas.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::{FiltroEfd, FiltrosEfd};
+///
+/// let filtros = FiltrosEfd {
+///     cst: vec!["50-66".parse().unwrap()],
+///     estabelecimento: Some("11111111000111".to_string()),
+///     ..Default::default()
+/// };
+///
+/// assert_eq!(filtros.ativos(), [FiltroEfd::Cst, FiltroEfd::Estabelecimento]);
+/// assert!(filtros.aceita(FiltroEfd::Cst, "56"));
+/// assert!(!filtros.aceita(FiltroEfd::Cst, "70"));
+/// assert!(filtros.aceita(FiltroEfd::Estabelecimento, "11.111.111/0001-11"));
+/// assert!(filtros.aceita(FiltroEfd::Cfop, "5102"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FiltrosEfd {
+    pub tipos_de_credito: Vec<FaixaDeCodigos>,
+    pub cst: Vec<FaixaDeCodigos>,
+    pub naturezas_bc: Vec<FaixaDeCodigos>,
+    pub cfop: Vec<FaixaDeCodigos>,
+    pub periodo: Option<IntervaloDePeriodos>,
+    /// CNPJ do estabelecimento, sem separadores.
+    pub estabelecimento: Option<String>,
+}
+
+impl FiltrosEfd {
+    /// Filtros informados, na ordem de aplicação.
+    pub fn ativos(&self) -> Vec<FiltroEfd> {
+        [
+            (FiltroEfd::TipoDeCredito, !self.tipos_de_credito.is_empty()),
+            (FiltroEfd::Cst, !self.cst.is_empty()),
+            (FiltroEfd::NaturezaBc, !self.naturezas_bc.is_empty()),
+            (FiltroEfd::Cfop, !self.cfop.is_empty()),
+            (FiltroEfd::Periodo, self.periodo.is_some()),
+            (FiltroEfd::Estabelecimento, self.estabelecimento.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(filtro, ativo)| ativo.then_some(filtro))
+        .collect()
+    }
+
+    /// Verifica se o valor do campo da EFD atende ao filtro.
+    pub fn aceita(&self, filtro: FiltroEfd, valor: &str) -> bool {
+        match filtro {
+            FiltroEfd::TipoDeCredito => pertence(&self.tipos_de_credito, valor),
+            FiltroEfd::Cst => pertence(&self.cst, valor),
+            FiltroEfd::NaturezaBc => pertence(&self.naturezas_bc, valor),
+            FiltroEfd::Cfop => pertence(&self.cfop, valor),
+            FiltroEfd::Periodo => self.periodo.is_none_or(|p| p.contem(valor)),
+            FiltroEfd::Estabelecimento => self.estabelecimento.as_ref().is_none_or(|cnpj| {
+                RE_NON_ALPHANUMERIC
+                    .replace_all(valor, "")
+                    .eq_ignore_ascii_case(cnpj)
+            }),
+        }
+    }
+
+    /// Valores informados para o filtro (ex: `50-66, 70`).
+    pub fn valores(&self, filtro: FiltroEfd) -> String {
+        let juntar = |faixas: &[FaixaDeCodigos]| {
+            faixas
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        match filtro {
+            FiltroEfd::TipoDeCredito => juntar(&self.tipos_de_credito),
+            FiltroEfd::Cst => juntar(&self.cst),
+            FiltroEfd::NaturezaBc => juntar(&self.naturezas_bc),
+            FiltroEfd::Cfop => juntar(&self.cfop),
+            FiltroEfd::Periodo => self.periodo.map(|p| p.to_string()).unwrap_or_default(),
+            FiltroEfd::Estabelecimento => self.estabelecimento.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Localiza as colunas dos filtros ativos no cabeçalho de um arquivo da EFD.
+    pub fn localizar<F>(&self, localizar: F) -> SpedResult<FiltrosLocalizados<'_>>
+    where
+        F: Fn(&'static str) -> SpedResult<usize>,
+    {
+        let colunas = self
+            .ativos()
+            .into_iter()
+            .map(|filtro| Ok((filtro, localizar(filtro.campo())?)))
+            .collect::<SpedResult<Vec<_>>>()?;
+
+        Ok(FiltrosLocalizados {
+            filtros: self,
+            colunas,
+        })
+    }
+}
+
+/// Código numérico inicial do campo (ex: `01 - Aquisição de bens` -> 1, `1.102` -> 1102).
+fn codigo_numerico(valor: &str) -> Option<u32> {
+    let digitos: String = valor
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .filter(char::is_ascii_digit)
+        .collect();
+
+    digitos.parse().ok()
+}
+
+fn pertence(faixas: &[FaixaDeCodigos], valor: &str) -> bool {
+    faixas.is_empty()
+        || codigo_numerico(valor).is_some_and(|codigo| faixas.iter().any(|f| f.contem(codigo)))
+}
+
+/// Filtros da EFD com as colunas localizadas no cabeçalho de um arquivo.
+#[derive(Debug)]
+pub struct FiltrosLocalizados<'a> {
+    filtros: &'a FiltrosEfd,
+    colunas: Vec<(FiltroEfd, usize)>,
+}
+
+impl FiltrosLocalizados<'_> {
+    /// Primeiro filtro que exclui a linha (`None` se a linha atende a todos os filtros).
+    pub fn excluir(&self, record: &StringRecord) -> Option<FiltroEfd> {
+        self.colunas
+            .iter()
+            .find(|&&(filtro, idx)| {
+                !self
+                    .filtros
+                    .aceita(filtro, record.get(idx).unwrap_or_default())
+            })
+            .map(|&(filtro, _)| filtro)
+    }
+}
+
+/// Imprime o número de linhas da EFD excluídas por cada filtro.
+///
+/// Cada linha é atribuída ao primeiro filtro (na ordem de aplicação) que a exclui.
+pub fn imprimir_linhas_excluidas(
+    filtros: &FiltrosEfd,
+    excluidas: &BTreeMap<FiltroEfd, usize>,
+    linhas_lidas: usize,
+    nome_efd: &str,
+) {
+    let ativos = filtros.ativos();
+    if ativos.is_empty() {
+        return;
+    }
+
+    println!(" Filtros aplicados às linhas da {nome_efd}:\n");
+
+    for filtro in ativos {
+        println!(
+            "  {:>9} linhas excluídas por {} ({} {})",
+            fmt_milhares(excluidas.get(&filtro).copied().unwrap_or_default()),
+            filtro,
+            filtro.opcao(),
+            filtros.valores(filtro)
+        );
+    }
+
+    let total_excluidas: usize = excluidas.values().sum();
+
+    println!(
+        "\n Linhas da {nome_efd} retidas após os filtros: {} de {}\n",
+        fmt_milhares(linhas_lidas - total_excluidas),
+        fmt_milhares(linhas_lidas)
+    );
+}
@@ -0,0 +1,307 @@
+use std::{
+    collections::{BTreeSet, HashSet, VecDeque},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{
+    ChaveAcesso, IndiceDeRelacoes, RE_NON_ALPHANUMERIC, SpedError, SpedResult, TipoDeRelacao,
+    fmt_milhares,
+};
+
+/// Abrangência da exportação do grafo de relações (opção `--grafo`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlvoDoGrafo {
+    /// Todos os componentes conectados (chaves com ao menos uma relação).
+    Todos,
+    /// Apenas o componente conectado que contém a chave.
+    Chave(ChaveAcesso),
+}
+
+impl FromStr for AlvoDoGrafo {
+    type Err = SpedError;
+
+    /// Converte `todos` ou uma chave de 44 posições (separadores são ignorados).
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::AlvoDoGrafo;
+    ///
+    /// assert_eq!("Todos".parse::<AlvoDoGrafo>().unwrap(), AlvoDoGrafo::Todos);
+    /// assert!("3524 0112 3456 7800 0190 5700 1000 0000 0110 0000 0017".parse::<AlvoDoGrafo>().is_ok());
+    /// assert!("123".parse::<AlvoDoGrafo>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("todos") {
+            return Ok(AlvoDoGrafo::Todos);
+        }
+
+        let chave = RE_NON_ALPHANUMERIC.replace_all(s, "").to_ascii_uppercase();
+        chave.parse().map(AlvoDoGrafo::Chave)
+    }
+}
+
+/// Componente conectado do grafo de relações entre chaves.
+#[derive(Debug, Default)]
+pub struct ComponenteDoGrafo {
+    pub chaves: BTreeSet<ChaveAcesso>,
+    /// Arestas não direcionadas: cada par é armazenado uma única vez (menor, maior).
+    pub arestas: BTreeSet<(ChaveAcesso, ChaveAcesso, TipoDeRelacao)>,
+}
+
+/// Componentes conectados do grafo formado pelas relações CTe -> NFes,
+/// CTe <-> CTe complementar e NFe <-> NFe referenciada.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::{
+///     AlvoDoGrafo, ChaveAcesso, IndiceDeRelacoes, KeyMap, componentes_do_grafo,
+/// };
+///
+/// let a: ChaveAcesso = "35240112345678000190570010000000011000000017".parse().unwrap();
+/// let b: ChaveAcesso = "35240112345678000190570010000000021000000022".parse().unwrap();
+/// let nfe: ChaveAcesso = "35240112345678000190550010000012341123456786".parse().unwrap();
+///
+/// let mut cte_nfes = KeyMap::new();
+/// cte_nfes.entry(a).or_default().insert(nfe);
+/// let mut complementar = KeyMap::new();
+/// complementar.entry(b).or_default().insert(a);
+///
+/// let indice = IndiceDeRelacoes::new(cte_nfes, complementar, KeyMap::new());
+///
+/// // A NFe alcança o CTe complementar B por meio do CTe A
+/// let componentes = componentes_do_grafo(&indice, AlvoDoGrafo::Chave(nfe));
+/// assert_eq!(componentes.len(), 1);
+/// assert_eq!(componentes[0].chaves.len(), 3);
+/// assert_eq!(componentes[0].arestas.len(), 2);
+/// ```
+pub fn componentes_do_grafo(
+    indice: &IndiceDeRelacoes,
+    alvo: AlvoDoGrafo,
+) -> Vec<ComponenteDoGrafo> {
+    let origens: BTreeSet<ChaveAcesso> = match alvo {
+        AlvoDoGrafo::Todos => indice.chaves_relacionadas().collect(),
+        AlvoDoGrafo::Chave(chave) => BTreeSet::from([chave]),
+    };
+
+    let mut visitadas: HashSet<ChaveAcesso> = HashSet::new();
+    let mut componentes = Vec::new();
+
+    for origem in origens {
+        if !visitadas.insert(origem) {
+            continue;
+        }
+
+        // Busca em largura a partir da origem
+        let mut componente = ComponenteDoGrafo::default();
+        let mut fila = VecDeque::from([origem]);
+
+        while let Some(chave) = fila.pop_front() {
+            componente.chaves.insert(chave);
+
+            for (vizinha, tipo) in indice.vizinhos(&chave) {
+                componente
+                    .arestas
+                    .insert((chave.min(vizinha), chave.max(vizinha), tipo));
+
+                if visitadas.insert(vizinha) {
+                    fila.push_back(vizinha);
+                }
+            }
+        }
+
+        componentes.push(componente);
+    }
+
+    componentes
+}
+
+/// Situação da chave na auditoria (anotação dos nós do grafo).
+struct Situacao {
+    declarada: bool,
+    encontrada: bool,
+}
+
+impl Situacao {
+    fn cor(&self) -> &'static str {
+        match (self.declarada, self.encontrada) {
+            (true, true) => "palegreen",
+            (true, false) => "salmon",
+            (false, true) => "lightblue",
+            (false, false) => "lightgray",
+        }
+    }
+}
+
+fn sim_nao(valor: bool) -> &'static str {
+    if valor { "sim" } else { "não" }
+}
+
+fn nome_da_relacao(tipo: TipoDeRelacao) -> &'static str {
+    match tipo {
+        TipoDeRelacao::Transporte => "transporte",
+        TipoDeRelacao::Complementar => "complementar",
+        TipoDeRelacao::Referencia => "referencia",
+    }
+}
+
+/// Exporta o grafo de relações, anotado com a presença das chaves, nos formatos DOT e GraphML.
+pub fn exportar_grafo(
+    indice: &IndiceDeRelacoes,
+    alvo: AlvoDoGrafo,
+    declaradas: &HashSet<ChaveAcesso>,
+    encontradas: &HashSet<ChaveAcesso>,
+    target_base: &Path,
+) -> SpedResult<()> {
+    let componentes = componentes_do_grafo(indice, alvo);
+
+    if let AlvoDoGrafo::Chave(chave) = alvo
+        && componentes.iter().all(|c| c.arestas.is_empty())
+    {
+        eprintln!(" [AVISO] A chave <{chave}> não possui relações com outras chaves.");
+    }
+
+    let situacao = |chave: &ChaveAcesso| Situacao {
+        declarada: declaradas.contains(chave),
+        encontrada: encontradas.contains(chave),
+    };
+
+    println!(
+        " Grafo de relações: {} componentes, {} chaves e {} arestas.\n",
+        fmt_milhares(componentes.len()),
+        fmt_milhares(componentes.iter().map(|c| c.chaves.len()).sum()),
+        fmt_milhares(componentes.iter().map(|c| c.arestas.len()).sum()),
+    );
+
+    let dot_path = format!("{}-Grafo de Relações.dot", target_base.display());
+    gravar_dot(&componentes, &situacao, Path::new(&dot_path))?;
+    println!(" ---> Novo arquivo do grafo de relações: <{}>", dot_path);
+
+    let graphml_path = format!("{}-Grafo de Relações.graphml", target_base.display());
+    gravar_graphml(&componentes, &situacao, Path::new(&graphml_path))?;
+    println!(
+        " ---> Novo arquivo do grafo de relações: <{}>\n",
+        graphml_path
+    );
+
+    Ok(())
+}
+
+fn gravar_dot(
+    componentes: &[ComponenteDoGrafo],
+    situacao: &dyn Fn(&ChaveAcesso) -> Situacao,
+    path: &Path,
+) -> SpedResult<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+
+    writeln!(w, "graph relacoes {{")?;
+    writeln!(w, "  node [style=filled, fontname=\"monospace\"];")?;
+
+    for (i, componente) in componentes.iter().enumerate() {
+        writeln!(w, "  subgraph cluster_{} {{", i + 1)?;
+        writeln!(
+            w,
+            "    label=\"Componente {} ({} chaves)\";",
+            i + 1,
+            componente.chaves.len()
+        )?;
+
+        for chave in &componente.chaves {
+            let s = situacao(chave);
+            // CTe: retângulo; NFe (e demais modelos): elipse
+            let shape = if chave.eh_modelo("57") {
+                "box"
+            } else {
+                "ellipse"
+            };
+
+            writeln!(
+                w,
+                "    \"{chave}\" [shape={shape}, fillcolor={}, label=\"Modelo {}\\n{chave}\\nEFD: {} | Docs: {}\"];",
+                s.cor(),
+                chave.modelo(),
+                sim_nao(s.declarada),
+                sim_nao(s.encontrada),
+            )?;
+        }
+
+        for (a, b, tipo) in &componente.arestas {
+            let estilo = match tipo {
+                TipoDeRelacao::Transporte => "solid",
+                TipoDeRelacao::Complementar => "dashed",
+                TipoDeRelacao::Referencia => "dotted",
+            };
+            writeln!(w, "    \"{a}\" -- \"{b}\" [style={estilo}];")?;
+        }
+
+        writeln!(w, "  }}")?;
+    }
+
+    writeln!(w, "}}")?;
+    w.flush()?;
+    Ok(())
+}
+
+fn gravar_graphml(
+    componentes: &[ComponenteDoGrafo],
+    situacao: &dyn Fn(&ChaveAcesso) -> Situacao,
+    path: &Path,
+) -> SpedResult<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+
+    for (id, domain, tipo) in [
+        ("modelo", "node", "string"),
+        ("componente", "node", "int"),
+        ("declarada_na_efd", "node", "boolean"),
+        ("encontrada_nos_docs", "node", "boolean"),
+        ("relacao", "edge", "string"),
+    ] {
+        writeln!(
+            w,
+            r#"  <key id="{id}" for="{domain}" attr.name="{id}" attr.type="{tipo}"/>"#
+        )?;
+    }
+
+    writeln!(w, r#"  <graph id="relacoes" edgedefault="undirected">"#)?;
+
+    for (i, componente) in componentes.iter().enumerate() {
+        for chave in &componente.chaves {
+            let s = situacao(chave);
+            writeln!(w, r#"    <node id="{chave}">"#)?;
+            writeln!(w, r#"      <data key="modelo">{}</data>"#, chave.modelo())?;
+            writeln!(w, r#"      <data key="componente">{}</data>"#, i + 1)?;
+            writeln!(
+                w,
+                r#"      <data key="declarada_na_efd">{}</data>"#,
+                s.declarada
+            )?;
+            writeln!(
+                w,
+                r#"      <data key="encontrada_nos_docs">{}</data>"#,
+                s.encontrada
+            )?;
+            writeln!(w, r#"    </node>"#)?;
+        }
+
+        for (a, b, tipo) in &componente.arestas {
+            writeln!(w, r#"    <edge source="{a}" target="{b}">"#)?;
+            writeln!(
+                w,
+                r#"      <data key="relacao">{}</data>"#,
+                nome_da_relacao(*tipo)
+            )?;
+            writeln!(w, r#"    </edge>"#)?;
+        }
+    }
+
+    writeln!(w, "  </graph>")?;
+    writeln!(w, "</graphml>")?;
+    w.flush()?;
+    Ok(())
+}
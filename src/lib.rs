@@ -1,7 +1,25 @@
+mod agrupamento;
 mod args;
+mod cancelada;
+mod chave;
+mod codificacao;
+mod conciliacao;
+mod correcao;
 mod error;
+mod filtro;
+mod grafo;
 mod metadata;
+mod periodo;
 mod regex;
+mod rejeicao;
+mod relacoes;
 mod sped_efd;
+mod sped_txt;
+mod xml_cte;
+mod xml_nfe;
 
-pub use self::{args::*, error::*, metadata::*, regex::*, sped_efd::*};
+pub use self::{
+    agrupamento::*, args::*, cancelada::*, chave::*, codificacao::*, conciliacao::*, correcao::*,
+    error::*, filtro::*, grafo::*, metadata::*, periodo::*, regex::*, rejeicao::*, relacoes::*,
+    sped_efd::*, sped_txt::*, xml_cte::*, xml_nfe::*,
+};
@@ -1,11 +1,20 @@
 use execution_time::ExecutionTime;
-use std::process;
+use std::{collections::HashSet, process};
 
 use reter_linhas_com_info_das_chaves::{
-    SpedResult, clear_screen, exibir_orientacoes_auditoria, expand_cte_complementar,
-    expand_cte_nfes, exportar_chaves_faltantes, get_config, get_efd_info, get_nfe_ctes,
-    imprimir_chaves_nao_encontradas, imprimir_informacao_segregada, imprimir_versao_do_programa,
-    ler_chave_complementar_deste_cte, ler_todas_as_nfes_deste_cte, merge_files, read_csv_files,
+    ChaveAcesso, Codificacao, IndiceDeRelacoes, SpedResult, buscar_correcoes_provaveis,
+    carregar_relacoes, clear_screen, converter_arquivos_efd_txt, exibir_orientacoes_auditoria,
+    exportar_agrupamento, exportar_chaves_faltantes, exportar_chaves_faltantes_por_arquivo,
+    exportar_chaves_invalidas, exportar_conciliacao_de_valores, exportar_correcoes_provaveis,
+    exportar_creditos_de_documentos_cancelados, exportar_grafo,
+    exportar_inconsistencias_de_periodo, exportar_linhas_rejeitadas,
+    exportar_origem_das_chaves_faltantes, get_config, get_creditos_de_documentos_cancelados,
+    get_efd_info, gravar_cte_complementar, gravar_cte_nfes, imprimir_chaves_nao_encontradas,
+    imprimir_documentos_sem_chave, imprimir_informacao_segregada, imprimir_linhas_excluidas,
+    imprimir_situacoes_nao_reconhecidas, imprimir_versao_do_programa,
+    ler_chave_complementar_deste_cte, ler_cte_nfes_dos_documentos,
+    ler_nfes_referenciadas_desta_nfe, ler_referencias_dos_xmls_de_nfe,
+    ler_relacoes_dos_xmls_de_cte, ler_todas_as_nfes_deste_cte, merge_files, read_csv_files,
 };
 
 fn main() {
@@ -28,50 +37,218 @@ fn run() -> SpedResult<()> {
 
     println!("Iniciando processamento SPED EFD em Rust...\n");
 
+    // Arquivos originais da EFD (SPED .txt): conversão para o formato CSV
+    converter_arquivos_efd_txt(&mut config.arquivos_efd, &config.target)?;
+
     // 3. Carregamento de Relacionamentos (Lógica funcional)
-    let file_cte = "cte_nfes.txt";
-    let mut cte_nfes = ler_todas_as_nfes_deste_cte(file_cte)?;
+    let (mut cte_nfes, cte_complementar, mut chaves_invalidas, mut linhas_rejeitadas) =
+        match &config.xml_cte {
+            // Relações obtidas diretamente dos XMLs de CT-e
+            Some(dir) => {
+                let relacoes = ler_relacoes_dos_xmls_de_cte(dir, config.codificacao)?;
+
+                if config.gravar_relacoes {
+                    if let Some(path) = &config.arquivo_cte_nfes {
+                        gravar_cte_nfes(&relacoes.cte_nfes, path)?;
+                    }
+                    if let Some(path) = &config.arquivo_cte_complementar {
+                        gravar_cte_complementar(&relacoes.cte_complementar, path)?;
+                    }
+                }
 
-    let file_comp = "transporte_subcontratado-chaves_complementares_dos_CTes.txt";
-    let mut cte_complementar = ler_chave_complementar_deste_cte(file_comp)?;
+                (
+                    relacoes.cte_nfes,
+                    relacoes.cte_complementar,
+                    relacoes.invalidas,
+                    Vec::new(),
+                )
+            }
+            // Arquivos opcionais: ausentes ou desativados resultam em relações vazias
+            None => {
+                let (cte_nfes, invalidas_cte, rejeitadas_cte) =
+                    carregar_relacoes(config.arquivo_cte_nfes.as_deref(), |path| {
+                        ler_todas_as_nfes_deste_cte(path, config.codificacao)
+                    })?;
 
-    // 4. Expansão das relações (Transitividade)
-    expand_cte_complementar(&mut cte_complementar);
+                let (cte_complementar, invalidas_comp, rejeitadas_comp) =
+                    carregar_relacoes(config.arquivo_cte_complementar.as_deref(), |path| {
+                        ler_chave_complementar_deste_cte(path, config.codificacao)
+                    })?;
 
-    // 5. Propagação de NFes para CTes complementares
-    expand_cte_nfes(&mut cte_nfes, &cte_complementar);
+                // Chaves com DV inválido e linhas descartadas são acumuladas para relatório próprio
+                (
+                    cte_nfes,
+                    cte_complementar,
+                    [invalidas_cte, invalidas_comp].concat(),
+                    [rejeitadas_cte, rejeitadas_comp].concat(),
+                )
+            }
+        };
 
-    // 6. Geração do índice invertido (NFe -> CTes)
-    let nfe_ctes = get_nfe_ctes(&cte_nfes);
+    // Relações NFe -> NFes referenciadas (devoluções, complementares e ajustes)
+    let (mut nfe_referencias, invalidas_ref, rejeitadas_ref) =
+        carregar_relacoes(config.arquivo_nfe_referenciadas.as_deref(), |path| {
+            ler_nfes_referenciadas_desta_nfe(path, config.codificacao)
+        })?;
+    chaves_invalidas.extend(invalidas_ref);
+    linhas_rejeitadas.extend(rejeitadas_ref);
+
+    if let Some(dir) = &config.referencias_dos_xmls_de_nfe {
+        for (nfe, refs) in ler_referencias_dos_xmls_de_nfe(dir, config.codificacao)? {
+            nfe_referencias.entry(nfe).or_default().extend(refs);
+        }
+    }
 
-    // 7. Injetar informações no config para uso em get_efd_info
-    config.nfe_ctes = nfe_ctes;
-    config.cte_nfes = cte_nfes;
-    config.cte_complementar = cte_complementar;
+    // Linhas descartadas dos arquivos de relações (para diagnóstico)
+    if config.gravar_rejeitadas {
+        exportar_linhas_rejeitadas(&linhas_rejeitadas, &config.target)?;
+    }
+
+    // Relações CTe -> NFe informadas nos próprios Documentos Fiscais (Colunas 1 e 2)
+    if config.relacoes_dos_documentos {
+        for (cte, nfes) in ler_cte_nfes_dos_documentos(&config)? {
+            cte_nfes.entry(cte).or_default().extend(nfes);
+        }
+    }
+
+    // 4. Índice de relações: componentes de CTes complementares (union-find)
+    // As NFes são propagadas aos CTes complementares na consulta, sem expansão em clique.
+    let relacoes = IndiceDeRelacoes::new(cte_nfes, cte_complementar, nfe_referencias);
+    relacoes.imprimir_estatisticas();
+
+    // 5. Injetar informações no config para uso em get_efd_info
+    config.relacoes = relacoes;
 
     if config.verbose {
         println!("{:#?}\n", config);
     }
 
-    // 8. Processamento EFD
-    let keys_efd = get_efd_info(&config)?;
+    // 6. Processamento EFD
+    let info_efd = get_efd_info(&config)?;
+    chaves_invalidas.extend(info_efd.invalidas.iter().cloned());
+    imprimir_linhas_excluidas(
+        &config.filtros,
+        &info_efd.linhas_excluidas,
+        info_efd.linhas_lidas,
+        config.escrituracao.nome(),
+    );
 
-    // 9. Exibir orientações e estatísticas da EFD
+    // 7. Exibir orientações e estatísticas da EFD
     exibir_orientacoes_auditoria(&config);
-    imprimir_informacao_segregada(&keys_efd, "EFD Contribuições", config.efd_keys);
+    imprimir_informacao_segregada(
+        &info_efd.chaves,
+        config.escrituracao.nome(),
+        config.efd_keys,
+    );
+
+    // 8. Processamento Documentos Fiscais (Paralelo)
+    let info_docs = read_csv_files(&config, &info_efd)?;
+    chaves_invalidas.extend(info_docs.invalidas.iter().cloned());
 
-    // 10. Processamento Documentos Fiscais (Paralelo)
-    let keys_doc = read_csv_files(&config, &keys_efd)?;
+    // 9. Consolidação
+    // Arquivo final em UTF-8 ou, com `--manter-codificacao`, na codificação original
+    let codificacao_de_saida = match info_docs.codificacao_original {
+        Some(original) if config.manter_codificacao => original,
+        _ => Codificacao::Utf8,
+    };
+    merge_files(&config, codificacao_de_saida)?;
+    imprimir_informacao_segregada(
+        &info_docs.encontradas,
+        "Documentos Fiscais",
+        config.docs_keys,
+    );
 
-    // 11. Consolidação
-    merge_files(&config)?;
-    imprimir_informacao_segregada(&keys_doc, "Documentos Fiscais", config.docs_keys);
+    // 10. Relatório Final de Ausências
+    let chaves_faltantes = imprimir_chaves_nao_encontradas(
+        &info_efd.chaves,
+        &info_docs.encontradas,
+        config.escrituracao.nome(),
+    );
 
-    // 12. Relatório Final de Ausências
-    let chaves_faltantes = imprimir_chaves_nao_encontradas(&keys_efd, &keys_doc);
+    if config.sem_chave {
+        imprimir_documentos_sem_chave(&info_efd, &info_docs, config.escrituracao.nome());
+    }
 
     if !chaves_faltantes.is_empty() {
         exportar_chaves_faltantes(&chaves_faltantes, &config.target)?;
+        exportar_origem_das_chaves_faltantes(&chaves_faltantes, &info_efd, &config.target)?;
+
+        // Vários arquivos da EFD (anos distintos ou retificadoras): relatório por arquivo
+        if config.arquivos_efd.len() > 1 {
+            exportar_chaves_faltantes_por_arquivo(
+                &chaves_faltantes,
+                &info_efd,
+                &config.arquivos_efd,
+                &config.target,
+            )?;
+        }
+    }
+
+    // Agrupamentos adicionais: UF, raiz do CNPJ do emitente e AAMM
+    for &agrupamento in &config.agrupamentos {
+        exportar_agrupamento(
+            agrupamento,
+            &info_efd.chaves,
+            &info_docs.encontradas,
+            config.escrituracao.nome(),
+            &config.target,
+        )?;
+    }
+
+    // Grafo de relações (nós anotados com a presença na EFD e nos Documentos Fiscais)
+    if let Some(alvo) = config.grafo {
+        exportar_grafo(
+            &config.relacoes,
+            alvo,
+            &info_efd.declaradas,
+            &info_docs.encontradas,
+            &config.target,
+        )?;
+    }
+
+    // 11. Relatório de Chaves com Dígito Verificador inválido
+    if !chaves_invalidas.is_empty() {
+        exportar_chaves_invalidas(&chaves_invalidas, &config.target)?;
+    }
+
+    // 12. Créditos da EFD sobre documentos cancelados
+    imprimir_situacoes_nao_reconhecidas(&info_docs.situacoes_nao_reconhecidas);
+    let creditos_cancelados =
+        get_creditos_de_documentos_cancelados(&config, &info_docs.canceladas)?;
+    exportar_creditos_de_documentos_cancelados(
+        &creditos_cancelados,
+        config.escrituracao,
+        &config.target,
+    )?;
+
+    // 13. Correções prováveis das chaves da EFD não encontradas (ou com DV inválido)
+    // Apenas as chaves declaradas na EFD: as correlacionadas (CTes/NFes) não foram digitadas.
+    if config.corrigir_chaves {
+        let nao_encontradas: HashSet<ChaveAcesso> = chaves_faltantes
+            .iter()
+            .filter(|chave| info_efd.declaradas.contains(chave))
+            .copied()
+            .chain(info_efd.invalidas.iter().map(|invalida| invalida.chave))
+            .collect();
+
+        let correcoes = buscar_correcoes_provaveis(&nao_encontradas, &info_docs.todas);
+        exportar_correcoes_provaveis(&correcoes, &config.target)?;
+    }
+
+    // 14. Consistência entre o AAMM das chaves e as datas da EFD
+    if config.verificar_periodos {
+        exportar_inconsistencias_de_periodo(&info_efd.inconsistencias_de_periodo, &config.target)?;
+    }
+
+    // 15. Conciliação de valores entre a EFD e os Documentos Fiscais
+    if let Some(conciliacao) = config.conciliacao {
+        exportar_conciliacao_de_valores(
+            &info_efd,
+            &info_docs,
+            config.escrituracao,
+            conciliacao,
+            &config.target,
+        )?;
     }
 
     println!(" Auditoria concluída com sucesso.\n");
@@ -49,6 +49,41 @@ pub fn get_modelo_documentos_fiscais(codigo: &str) -> &'static str {
     }
 }
 
+/// Unidades Federativas - Códigos do IBGE (cUF da chave de acesso)
+/// Otimizado para não usar memória RAM (armazenado no binário)
+pub fn get_unidade_federativa(codigo: &str) -> &'static str {
+    match codigo {
+        "11" => "RO",
+        "12" => "AC",
+        "13" => "AM",
+        "14" => "RR",
+        "15" => "PA",
+        "16" => "AP",
+        "17" => "TO",
+        "21" => "MA",
+        "22" => "PI",
+        "23" => "CE",
+        "24" => "RN",
+        "25" => "PB",
+        "26" => "PE",
+        "27" => "AL",
+        "28" => "SE",
+        "29" => "BA",
+        "31" => "MG",
+        "32" => "ES",
+        "33" => "RJ",
+        "35" => "SP",
+        "41" => "PR",
+        "42" => "SC",
+        "43" => "RS",
+        "50" => "MS",
+        "51" => "MT",
+        "52" => "GO",
+        "53" => "DF",
+        _ => "UF Desconhecida",
+    }
+}
+
 // Mapeamento estático para colunas EFD
 pub static COLUNAS_EFD: LazyLock<HashMap<&'static str, &'static str>> = LazyLock::new(|| {
     HashMap::from([
@@ -112,6 +147,84 @@ pub static COLUNAS_EFD: LazyLock<HashMap<&'static str, &'static str>> = LazyLock
     ])
 });
 
+// Mapeamento estático para colunas EFD ICMS/IPI (registros C100/C170 e D100)
+pub static COLUNAS_EFD_ICMS_IPI: LazyLock<HashMap<&'static str, &'static str>> =
+    LazyLock::new(|| {
+        HashMap::from([
+            ("num_linha", "Linhas"),
+            ("efd_file", "Arquivo da EFD ICMS/IPI"),
+            ("efd_line", "Nº da Linha da EFD"),
+            ("cnpj_contribuinte", "CNPJ do Estabelecimento"),
+            ("nome_contribuinte", "Nome do Contribuinte"),
+            ("periodo_apuracao", "Período de Apuração"),
+            ("tipo_de_operacao", "Tipo de Operação"),
+            ("registro_bloco", "Registro"),
+            ("codigo_cst", "CST ICMS"),
+            ("codigo_cfop", "CFOP"),
+            ("cnpj_participante", "CNPJ do Participante"),
+            ("cpf_participante", "CPF do Participante"),
+            ("nome_participante", "Nome do Participante"),
+            ("num_doc_fiscal", "Nº do Documento Fiscal"),
+            ("chave_documento", "Chave do Documento"),
+            ("modelo_doc_fiscal", "Modelo do Documento Fiscal"),
+            ("num_item", "Nº do Item do Documento Fiscal"),
+            ("data_emissao_nota", "Data da Emissão do Documento Fiscal"),
+            ("data_lancamento", "Data da Entrada / Saída"),
+            ("descricao_do_item", "Descrição do Item"),
+            ("codigo_ncm", "Código NCM"),
+            ("valor_do_item", "Valor Total do Item"),
+            ("valor_bc_icms", "Valor da Base de Cálculo de ICMS"),
+            ("aliq_icms", "Alíquota de ICMS (em percentual)"),
+            ("valor_de_icms", "Valor de ICMS"),
+            ("valor_bc_icms_st", "Valor da Base de Cálculo de ICMS ST"),
+            ("valor_de_icms_st", "Valor de ICMS ST"),
+            ("valor_de_ipi", "Valor de IPI"),
+        ])
+    });
+
+/// Ordem das colunas (chaves de `COLUNAS_EFD`) no arquivo CSV da EFD Contribuições.
+pub const ORDEM_COLUNAS_EFD: [&str; 39] = [
+    "num_linha",
+    "efd_file",
+    "efd_line",
+    "cnpj_contribuinte",
+    "nome_contribuinte",
+    "periodo_apuracao",
+    "periodo_apuracao_ano",
+    "periodo_apuracao_tri",
+    "periodo_apuracao_mes",
+    "tipo_de_operacao",
+    "tipo_de_credito",
+    "registro_bloco",
+    "codigo_cst",
+    "codigo_cfop",
+    "natureza_bc",
+    "cnpj_participante",
+    "cpf_participante",
+    "nome_participante",
+    "num_doc_fiscal",
+    "chave_documento",
+    "modelo_doc_fiscal",
+    "num_item",
+    "data_emissao_nota",
+    "data_lancamento",
+    "tipo_do_item",
+    "descricao_do_item",
+    "codigo_ncm",
+    "escrituracao_contabil",
+    "info_complem_doc_fiscal",
+    "valor_do_item",
+    "valor_bc_contrib",
+    "aliq_pis",
+    "aliq_cofins",
+    "valor_de_pis",
+    "valor_de_cofins",
+    "valor_de_iss",
+    "valor_bc_icms",
+    "aliq_icms",
+    "valor_de_icms",
+];
+
 // Mapeamento estático para colunas DOC
 pub static COLUNAS_DOC: LazyLock<HashMap<&'static str, &'static str>> = LazyLock::new(|| {
     HashMap::from([
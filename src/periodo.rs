@@ -0,0 +1,251 @@
+use std::{
+    fmt,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use crate::{ChaveAcesso, SpedResult, fmt_milhares};
+
+/// Ano e mês (competência) de uma data, período de apuração ou chave de acesso.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AnoMes {
+    pub ano: u16,
+    pub mes: u8,
+}
+
+impl AnoMes {
+    fn new(ano: u16, mes: u8) -> Option<Self> {
+        (1..=12).contains(&mes).then_some(AnoMes { ano, mes })
+    }
+
+    /// Ano e mês de emissão contidos na chave (AAMM: posições 3 a 6).
+    pub fn from_chave(chave: &ChaveAcesso) -> Option<Self> {
+        let aamm = chave.aamm();
+        AnoMes::new(2000 + aamm / 100, (aamm % 100) as u8)
+    }
+
+    /// Extrai ano e mês de datas ou períodos nos formatos usuais da EFD.
+    ///
+    /// Formatos aceitos: `DD/MM/AAAA`, `MM/AAAA`, `AAAA-MM-DD`, `AAAA-MM`,
+    /// `DDMMAAAA`, `AAAAMM` e `MMAAAA`.
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::AnoMes;
+    ///
+    /// let esperado = Some(AnoMes { ano: 2024, mes: 1 });
+    ///
+    /// assert_eq!(AnoMes::parse("15/01/2024"), esperado);
+    /// assert_eq!(AnoMes::parse("01/2024"), esperado);
+    /// assert_eq!(AnoMes::parse("2024-01-15"), esperado);
+    /// assert_eq!(AnoMes::parse("15012024"), esperado);
+    /// assert_eq!(AnoMes::parse("202401"), esperado);
+    /// assert_eq!(AnoMes::parse("012024"), esperado);
+    /// assert_eq!(AnoMes::parse(""), None);
+    /// ```
+    pub fn parse(texto: &str) -> Option<Self> {
+        let texto = texto.trim();
+        let partes: Vec<&str> = texto.split(['/', '-']).map(str::trim).collect();
+
+        let (ano, mes) = match partes[..] {
+            [_dia, mes, ano] if texto.contains('/') => (ano, mes),
+            [mes, ano] if texto.contains('/') => (ano, mes),
+            [ano, mes, _] | [ano, mes] => (ano, mes),
+            [digitos] => match digitos.len() {
+                8 => (digitos.get(4..8)?, digitos.get(2..4)?),
+                6 if digitos.starts_with("19") || digitos.starts_with("20") => {
+                    (digitos.get(0..4)?, digitos.get(4..6)?)
+                }
+                6 => (digitos.get(2..6)?, digitos.get(0..2)?),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        AnoMes::new(ano.parse().ok()?, mes.parse().ok()?)
+    }
+
+    /// Número de meses decorridos de `self` até `outro` (negativo se `outro` for anterior).
+    pub fn meses_ate(&self, outro: &AnoMes) -> i32 {
+        (i32::from(outro.ano) * 12 + i32::from(outro.mes))
+            - (i32::from(self.ano) * 12 + i32::from(self.mes))
+    }
+}
+
+impl fmt::Display for AnoMes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}/{:04}", self.mes, self.ano)
+    }
+}
+
+/// Motivo da inconsistência entre o AAMM da chave e as datas da EFD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MotivoDeInconsistencia {
+    /// O AAMM da chave difere do ano/mês da data de emissão informada na EFD.
+    DataDeEmissao,
+    /// A chave foi emitida após a data de lançamento (entrada/saída) informada na EFD.
+    EmissaoAposLancamento,
+    /// A chave foi emitida muitos meses antes do período de apuração.
+    EmissaoAntiga,
+    /// A chave foi emitida após o período de apuração.
+    EmissaoPosterior,
+}
+
+impl fmt::Display for MotivoDeInconsistencia {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let descricao = match self {
+            MotivoDeInconsistencia::DataDeEmissao => "AAMM da chave difere da data de emissão",
+            MotivoDeInconsistencia::EmissaoAposLancamento => {
+                "Chave emitida após a data de lançamento"
+            }
+            MotivoDeInconsistencia::EmissaoAntiga => {
+                "Chave emitida muito antes do período de apuração"
+            }
+            MotivoDeInconsistencia::EmissaoPosterior => "Chave emitida após o período de apuração",
+        };
+        f.write_str(descricao)
+    }
+}
+
+/// Linha da EFD cujo AAMM da chave é inconsistente com as datas informadas.
+#[derive(Debug, Clone)]
+pub struct InconsistenciaDePeriodo {
+    pub chave: ChaveAcesso,
+    /// Arquivo CSV da EFD.
+    pub arquivo: PathBuf,
+    /// Número da linha no arquivo CSV da EFD.
+    pub linha: usize,
+    /// Nº da Linha da EFD (arquivo SPED original).
+    pub efd_line: String,
+    pub data_emissao: String,
+    pub data_lancamento: String,
+    pub periodo_apuracao: String,
+    /// Meses decorridos entre o AAMM da chave e a data comparada
+    /// (emissão, lançamento ou período de apuração, conforme o motivo).
+    pub defasagem: Option<i32>,
+    pub motivo: MotivoDeInconsistencia,
+}
+
+/// Compara o AAMM da chave com as datas de emissão e de lançamento e com o período de apuração.
+///
+/// São apontadas as linhas em que:
+/// - o AAMM da chave difere do ano/mês da data de emissão;
+/// - a chave foi emitida após o ano/mês da data de lançamento (entrada/saída);
+/// - a chave foi emitida mais de `meses_defasagem` meses antes do período de apuração;
+/// - a chave foi emitida após o período de apuração.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::{verificar_periodo, ChaveAcesso, MotivoDeInconsistencia};
+///
+/// // AAMM da chave: 01/2024
+/// let chave: ChaveAcesso = "35240112345678000190550010000012341123456786".parse().unwrap();
+///
+/// assert!(verificar_periodo(&chave, "15/01/2024", "20/01/2024", "01/2024", 12).is_empty());
+///
+/// let motivos = verificar_periodo(&chave, "15/02/2024", "10/12/2023", "03/2026", 12);
+/// assert_eq!(
+///     motivos,
+///     [
+///         (MotivoDeInconsistencia::DataDeEmissao, Some(1)),
+///         (MotivoDeInconsistencia::EmissaoAposLancamento, Some(-1)),
+///         (MotivoDeInconsistencia::EmissaoAntiga, Some(26)),
+///     ]
+/// );
+/// ```
+pub fn verificar_periodo(
+    chave: &ChaveAcesso,
+    data_emissao: &str,
+    data_lancamento: &str,
+    periodo_apuracao: &str,
+    meses_defasagem: u32,
+) -> Vec<(MotivoDeInconsistencia, Option<i32>)> {
+    let Some(aamm) = AnoMes::from_chave(chave) else {
+        return Vec::new();
+    };
+
+    let defasagem = AnoMes::parse(periodo_apuracao).map(|periodo| aamm.meses_ate(&periodo));
+    let mut motivos = Vec::new();
+
+    if let Some(emissao) = AnoMes::parse(data_emissao)
+        && emissao != aamm
+    {
+        let meses = aamm.meses_ate(&emissao);
+        motivos.push((MotivoDeInconsistencia::DataDeEmissao, Some(meses)));
+    }
+
+    if let Some(lancamento) = AnoMes::parse(data_lancamento)
+        && lancamento < aamm
+    {
+        let meses = aamm.meses_ate(&lancamento);
+        motivos.push((MotivoDeInconsistencia::EmissaoAposLancamento, Some(meses)));
+    }
+
+    match defasagem {
+        Some(meses) if meses < 0 => {
+            motivos.push((MotivoDeInconsistencia::EmissaoPosterior, defasagem));
+        }
+        Some(meses) if meses.unsigned_abs() > meses_defasagem => {
+            motivos.push((MotivoDeInconsistencia::EmissaoAntiga, defasagem));
+        }
+        _ => {}
+    }
+
+    motivos
+}
+
+/// Imprime o resumo e exporta as inconsistências de período em `<target>-Inconsistências de Período.csv`.
+pub fn exportar_inconsistencias_de_periodo(
+    inconsistencias: &[InconsistenciaDePeriodo],
+    target_base: &Path,
+) -> SpedResult<()> {
+    println!(
+        " Número de linhas da EFD com AAMM da chave inconsistente com o período: {}\n",
+        fmt_milhares(inconsistencias.len())
+    );
+
+    if inconsistencias.is_empty() {
+        return Ok(());
+    }
+
+    let file_path = format!("{}-Inconsistências de Período.csv", target_base.display());
+
+    println!(
+        " ---> Novo arquivo de inconsistências de período: <{}>",
+        file_path
+    );
+
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(BufWriter::new(File::create(&file_path)?));
+
+    wtr.write_record([
+        "Arquivo da EFD",
+        "Linha",
+        "Nº da Linha da EFD",
+        "Chave",
+        "AAMM da Chave",
+        "Data da Emissão",
+        "Data de Lançamento",
+        "Período de Apuração",
+        "Defasagem (meses)",
+        "Motivo",
+    ])?;
+
+    for inc in inconsistencias {
+        wtr.write_record([
+            &inc.arquivo.display().to_string(),
+            &inc.linha.to_string(),
+            &inc.efd_line,
+            &inc.chave.to_string(),
+            &format!("{:04}", inc.chave.aamm()),
+            &inc.data_emissao,
+            &inc.data_lancamento,
+            &inc.periodo_apuracao,
+            &inc.defasagem.map(|d| d.to_string()).unwrap_or_default(),
+            &inc.motivo.to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
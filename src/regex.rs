@@ -26,5 +26,18 @@ pub static REGEX_SEARCH_CSV: LazyLock<Regex> = LazyLock::new(|| {
 
 // Regex para limpeza e validação
 pub static RE_MULTISPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s{2,}").unwrap());
-pub static RE_NON_DIGITS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\D").unwrap());
-pub static RE_CHAVE_44: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d{44})$").unwrap());
+pub static RE_NON_ALPHANUMERIC: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[^0-9A-Za-z]").unwrap());
+
+/// Chave de 44 posições: a partir de julho de 2026, as 12 primeiras posições do
+/// CNPJ do emitente (posições 7 a 18 da chave) podem conter letras maiúsculas.
+/// As demais posições, inclusive os 2 dígitos verificadores do CNPJ, são numéricas.
+///
+/// Usa `[0-9]` em vez de `\d`: no crate regex, `\d` aceita qualquer dígito Unicode.
+pub static RE_CHAVE_44: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([0-9]{6}[0-9A-Z]{12}[0-9]{26})$").unwrap());
+
+/// Chaves de 44 posições isoladas em uma linha de texto (arquivos de relações).
+/// \b garante que pegamos apenas sequências de 44 posições isoladas.
+pub static RE_CHAVES_NA_LINHA: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b[0-9]{6}[0-9A-Z]{12}[0-9]{26}\b").unwrap());
@@ -0,0 +1,134 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use crate::{SpedResult, fmt_milhares};
+
+/// Motivo pelo qual uma linha de um arquivo de relações foi descartada.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::MotivoDeRejeicao;
+///
+/// assert_eq!(
+///     MotivoDeRejeicao::AutoReferencia.to_string(),
+///     "CTe complementar igual ao CTe original"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MotivoDeRejeicao {
+    /// Nenhuma chave de 44 posições na linha.
+    SemChaves,
+    /// Arquivo `cte_nfes`: a primeira chave não é de CTe (modelo 57).
+    PrimeiraChaveNaoCte,
+    /// Arquivo `cte_nfes` (ou de NFes referenciadas): nenhuma NFe (modelo 55) com DV válido
+    /// após a primeira chave.
+    SemNfes,
+    /// Arquivo de NFes referenciadas: a primeira chave não é de NFe (modelo 55).
+    PrimeiraChaveNaoNfe,
+    /// Arquivo de CTes complementares: menos de duas chaves na linha.
+    MenosDeDuasChaves,
+    /// Arquivo de CTes complementares: uma das chaves não é de CTe (modelo 57).
+    ChaveNaoCte,
+    /// Primeira chave da linha (ou do par de CTes) com DV inválido.
+    DvInvalido,
+    /// Arquivo de CTes complementares: o CTe complementa a si mesmo.
+    AutoReferencia,
+}
+
+impl fmt::Display for MotivoDeRejeicao {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let descricao = match self {
+            MotivoDeRejeicao::SemChaves => "Linha sem chaves de 44 posições",
+            MotivoDeRejeicao::PrimeiraChaveNaoCte => "Primeira chave não é de CTe (modelo 57)",
+            MotivoDeRejeicao::SemNfes => "Nenhuma NFe (modelo 55) com DV válido",
+            MotivoDeRejeicao::PrimeiraChaveNaoNfe => "Primeira chave não é de NFe (modelo 55)",
+            MotivoDeRejeicao::MenosDeDuasChaves => "Menos de duas chaves de 44 posições",
+            MotivoDeRejeicao::ChaveNaoCte => "Chave que não é de CTe (modelo 57)",
+            MotivoDeRejeicao::DvInvalido => "Chave com DV inválido",
+            MotivoDeRejeicao::AutoReferencia => "CTe complementar igual ao CTe original",
+        };
+        f.write_str(descricao)
+    }
+}
+
+/// Linha descartada de um arquivo de relações (CTe -> NFes, CTe -> CTe complementar
+/// ou NFe -> NFes referenciadas).
+#[derive(Debug, Clone)]
+pub struct LinhaRejeitada {
+    pub arquivo: PathBuf,
+    pub linha: usize,
+    pub motivo: MotivoDeRejeicao,
+    /// Conteúdo original da linha.
+    pub conteudo: String,
+}
+
+/// Imprime o número de linhas descartadas do arquivo de relações, por motivo.
+pub fn imprimir_linhas_rejeitadas(rejeitadas: &[LinhaRejeitada], arquivo: &Path) {
+    if rejeitadas.is_empty() {
+        return;
+    }
+
+    let contagem =
+        rejeitadas
+            .iter()
+            .fold(BTreeMap::<MotivoDeRejeicao, usize>::new(), |mut acc, r| {
+                *acc.entry(r.motivo).or_default() += 1;
+                acc
+            });
+
+    eprintln!(
+        " [AVISO] {} linhas descartadas do arquivo <{}>:",
+        fmt_milhares(rejeitadas.len()),
+        arquivo.display()
+    );
+
+    for (motivo, num) in contagem {
+        eprintln!("  {:>9} : {}", fmt_milhares(num), motivo);
+    }
+}
+
+/// Exporta as linhas descartadas dos arquivos de relações em `<target>-Linhas Rejeitadas das Relações.csv`.
+pub fn exportar_linhas_rejeitadas(
+    rejeitadas: &[LinhaRejeitada],
+    target_base: &Path,
+) -> SpedResult<()> {
+    if rejeitadas.is_empty() {
+        return Ok(());
+    }
+
+    // Ordenação por arquivo e linha (a leitura paralela não preserva a ordem)
+    let mut sorted: Vec<&LinhaRejeitada> = rejeitadas.iter().collect();
+    sorted.sort_unstable_by_key(|r| (&r.arquivo, r.linha));
+
+    let file_path = format!(
+        "{}-Linhas Rejeitadas das Relações.csv",
+        target_base.display()
+    );
+
+    println!(
+        " ---> Novo arquivo de linhas rejeitadas das relações: <{}>\n",
+        file_path
+    );
+
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(BufWriter::new(File::create(&file_path)?));
+
+    wtr.write_record(["Arquivo", "Linha", "Motivo", "Conteúdo"])?;
+
+    for r in sorted {
+        wtr.write_record([
+            r.arquivo.display().to_string(),
+            r.linha.to_string(),
+            r.motivo.to_string(),
+            r.conteudo.clone(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,752 @@
+use clap::ValueEnum;
+use std::{
+    collections::{HashMap, HashSet, hash_map::Entry},
+    fmt,
+};
+
+use crate::{ChaveAcesso, KeyMap, fmt_milhares, get_nfe_ctes};
+
+/// Chaves relacionadas diretamente à chave (um salto).
+fn diretas<'a>(mapa: &'a KeyMap, chave: &ChaveAcesso) -> impl Iterator<Item = ChaveAcesso> + 'a {
+    mapa.get(chave).into_iter().flatten().copied()
+}
+
+/// Estrutura de conjuntos disjuntos (union-find) sobre índices `0..n`.
+///
+/// Utiliza compressão de caminho (path halving) e união por tamanho:
+/// cada operação tem custo amortizado praticamente constante.
+#[derive(Debug)]
+struct UniaoBusca {
+    pai: Vec<usize>,
+    tamanho: Vec<usize>,
+}
+
+impl UniaoBusca {
+    fn new(n: usize) -> Self {
+        UniaoBusca {
+            pai: (0..n).collect(),
+            tamanho: vec![1; n],
+        }
+    }
+
+    fn raiz(&mut self, mut i: usize) -> usize {
+        while self.pai[i] != i {
+            self.pai[i] = self.pai[self.pai[i]];
+            i = self.pai[i];
+        }
+        i
+    }
+
+    fn unir(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.raiz(a), self.raiz(b));
+        if a == b {
+            return;
+        }
+        if self.tamanho[a] < self.tamanho[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.pai[b] = a;
+        self.tamanho[a] += self.tamanho[b];
+    }
+}
+
+/// Índice das relações entre chaves (CTe -> NFes, CTe <-> CTe complementar e
+/// NFe <-> NFe referenciada).
+///
+/// Os CTes complementares (transporte subcontratado) são agrupados em componentes
+/// conectados por meio de union-find: cada CTe recebe o identificador do seu componente
+/// e cada componente armazena a lista dos seus membros uma única vez.
+///
+/// Diferente de uma expansão em clique, em que cada membro armazenaria todos os
+/// demais (O(k²) chaves por componente), o índice ocupa O(k) e as NFes herdadas
+/// pelos CTes complementares são obtidas na consulta, sem cópias.
+///
+/// ### Exemplo
+/// ```
+/// use reter_linhas_com_info_das_chaves::{ChaveAcesso, IndiceDeRelacoes, KeyMap};
+/// use std::collections::HashSet;
+///
+/// let a: ChaveAcesso = "35240112345678000190570010000000011000000017".parse().unwrap();
+/// let b: ChaveAcesso = "35240112345678000190570010000000021000000022".parse().unwrap();
+/// let c: ChaveAcesso = "35240112345678000190570010000000031000000038".parse().unwrap();
+/// let nfe: ChaveAcesso = "35240112345678000190550010000012341123456786".parse().unwrap();
+///
+/// // Cadeia de subcontratação: A -> B -> C; a NFe é transportada pelo CTe A
+/// let mut complementar = KeyMap::new();
+/// complementar.entry(a).or_default().insert(b);
+/// complementar.entry(b).or_default().insert(c);
+///
+/// let mut cte_nfes = KeyMap::new();
+/// cte_nfes.entry(a).or_default().insert(nfe);
+///
+/// let indice = IndiceDeRelacoes::new(cte_nfes, complementar, KeyMap::new());
+///
+/// assert_eq!(indice.componente(&a), indice.componente(&c));
+/// assert_eq!(indice.num_componentes(), 1);
+///
+/// // O CTe C herda a NFe do CTe A; a NFe está vinculada aos três CTes
+/// assert!(indice.nfes_do_cte(&c).any(|n| n == nfe));
+/// assert_eq!(indice.ctes_da_nfe(&nfe).collect::<HashSet<_>>(), HashSet::from([a, b, c]));
+///
+/// // Vizinhos diretos nos dois sentidos da relação (B é complementado por A e complementa C)
+/// let complementares_de_b: HashSet<_> = indice.vizinhos(&b).map(|(vizinha, _)| vizinha).collect();
+/// assert_eq!(complementares_de_b, HashSet::from([a, c]));
+/// ```
+#[derive(Debug, Default)]
+pub struct IndiceDeRelacoes {
+    /// CTe complementar -> posição nos vetores `componentes` e `adjacentes`.
+    posicao: HashMap<ChaveAcesso, usize>,
+    /// Identificador do componente de cada posição.
+    componentes: Vec<usize>,
+    /// CTes complementares diretos de cada posição, nos dois sentidos da relação.
+    /// Cada par informado é armazenado uma única vez em cada extremidade (posições, não chaves).
+    adjacentes: Vec<Vec<usize>>,
+    /// Chave de cada posição.
+    chaves: Vec<ChaveAcesso>,
+    /// Membros de cada componente.
+    membros: Vec<Vec<ChaveAcesso>>,
+    /// CTe -> NFes (relação direta, sem herança).
+    cte_nfes: KeyMap,
+    /// NFe -> CTes (índice invertido da relação direta).
+    nfe_ctes: KeyMap,
+    /// NFe <-> NFe referenciada (`refNFe`: devoluções, complementares e ajustes; simétrica).
+    referencias: KeyMap,
+}
+
+/// Tipo de relação (aresta) entre duas chaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TipoDeRelacao {
+    /// CTe que transporta a NFe.
+    Transporte,
+    /// CTe original e CTe complementar (subcontratação).
+    Complementar,
+    /// NFe e NFe referenciada (`refNFe`).
+    Referencia,
+}
+
+/// Motivo pelo qual a chave (ou a linha de Documento Fiscal) foi retida.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::{ChaveAcesso, Motivo};
+///
+/// let cte: ChaveAcesso = "35240112345678000190570010000000011000000017".parse().unwrap();
+///
+/// assert_eq!(Motivo::Declarada.to_string(), "Chave declarada na EFD");
+/// assert_eq!(
+///     Motivo::NfeDoCte(cte).to_string(),
+///     "NFe transportada pelo CTe 35240112345678000190570010000000011000000017"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Motivo {
+    /// Chave declarada diretamente na EFD.
+    Declarada,
+    /// NFe transportada pelo CTe declarado (inclusive por CTe complementar).
+    NfeDoCte(ChaveAcesso),
+    /// CTe que transporta a NFe declarada (e seus complementares).
+    CteDaNfe(ChaveAcesso),
+    /// CTe do mesmo componente de CTes complementares do CTe declarado.
+    Complementar(ChaveAcesso),
+    /// NFe que referencia a NFe declarada (ou é referenciada por ela).
+    Referenciada(ChaveAcesso),
+    /// Documento sem chave encontrado por CNPJ + Número (`--sem-chave`).
+    SemChave,
+}
+
+impl Motivo {
+    /// Chave declarada diretamente na EFD (não correlacionada).
+    pub fn eh_declarada(&self) -> bool {
+        matches!(self, Motivo::Declarada)
+    }
+}
+
+impl fmt::Display for Motivo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Motivo::Declarada => write!(f, "Chave declarada na EFD"),
+            Motivo::NfeDoCte(cte) => write!(f, "NFe transportada pelo CTe {cte}"),
+            Motivo::CteDaNfe(nfe) => write!(f, "CTe que transporta a NFe {nfe}"),
+            Motivo::Complementar(cte) => write!(f, "CTe complementar do CTe {cte}"),
+            Motivo::Referenciada(nfe) => write!(f, "NFe com referência (refNFe) à NFe {nfe}"),
+            Motivo::SemChave => write!(f, "Documento sem chave: CNPJ + Número"),
+        }
+    }
+}
+
+/// Regras de inclusão de chaves no filtro (ver `exibir_orientacoes_auditoria`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Regra {
+    /// a) Chave declarada na EFD procurada na Coluna 1 dos Docs Fiscais.
+    #[value(alias = "a")]
+    ChaveDireta,
+    /// b) NFe declarada na EFD procurada na Coluna 2 (CTe com uma única NFe).
+    #[value(alias = "b")]
+    CteNfeUnica,
+    /// c) NFe vinculada a CTe (relações CTe -> NFes).
+    #[value(alias = "c")]
+    NfeViaCte,
+    /// d) CTe original e CTe complementar (subcontratação).
+    #[value(alias = "d")]
+    CteComplementar,
+    /// e) NFe referenciada por outra NFe (devoluções, complementares e ajustes).
+    #[value(alias = "e")]
+    NfeReferenciada,
+}
+
+/// Regras de inclusão aplicadas na auditoria.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::{Regra, RegrasDeInclusao};
+///
+/// let regras = RegrasDeInclusao::new(&[Regra::CteComplementar], true);
+///
+/// assert!(regras.chave_direta && regras.nfe_via_cte);
+/// assert!(!regras.cte_complementar);
+/// assert!(regras.um_salto);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegrasDeInclusao {
+    pub chave_direta: bool,
+    pub cte_nfe_unica: bool,
+    pub nfe_via_cte: bool,
+    pub cte_complementar: bool,
+    pub nfe_referenciada: bool,
+    /// Apenas relações diretas (um salto), sem o fecho transitivo dos componentes.
+    pub um_salto: bool,
+}
+
+impl Default for RegrasDeInclusao {
+    fn default() -> Self {
+        RegrasDeInclusao::new(&[], false)
+    }
+}
+
+impl RegrasDeInclusao {
+    /// Todas as regras, exceto as desativadas.
+    pub fn new(desativadas: &[Regra], um_salto: bool) -> Self {
+        let ativa = |regra| !desativadas.contains(&regra);
+
+        RegrasDeInclusao {
+            chave_direta: ativa(Regra::ChaveDireta),
+            cte_nfe_unica: ativa(Regra::CteNfeUnica),
+            nfe_via_cte: ativa(Regra::NfeViaCte),
+            cte_complementar: ativa(Regra::CteComplementar),
+            nfe_referenciada: ativa(Regra::NfeReferenciada),
+            um_salto,
+        }
+    }
+}
+
+/// Componentes já incluídos no filtro de chaves.
+///
+/// Evita que um mesmo componente seja percorrido novamente para cada chave da EFD.
+#[derive(Debug, Default)]
+pub struct ComponentesIncluidos {
+    /// Componentes cujos membros já foram incluídos e o CTe declarado que ficou pendente.
+    membros: HashMap<usize, Option<ChaveAcesso>>,
+    nfes: HashSet<usize>,
+}
+
+impl IndiceDeRelacoes {
+    /// Constrói o índice a partir das relações CTe -> NFes, CTe -> CTe complementar
+    /// e NFe -> NFes referenciadas.
+    pub fn new(cte_nfes: KeyMap, cte_complementar: KeyMap, nfe_referencias: KeyMap) -> Self {
+        // 1. Índice numérico de cada CTe com complemento
+        let mut indice: HashMap<ChaveAcesso, usize> = HashMap::new();
+        let mut chaves: Vec<ChaveAcesso> = Vec::new();
+
+        let mut posicao = |chave: ChaveAcesso| {
+            *indice.entry(chave).or_insert_with(|| {
+                chaves.push(chave);
+                chaves.len() - 1
+            })
+        };
+
+        // Relação simétrica: se A complementa B, B também é vinculado a A
+        let mut arestas: Vec<(usize, usize)> = Vec::new();
+
+        for (cte, comps) in cte_complementar {
+            for comp in comps.into_iter().filter(|&comp| comp != cte) {
+                arestas.push((posicao(cte), posicao(comp)));
+            }
+        }
+
+        // 2. União dos CTes relacionados (direta ou indiretamente) e vizinhos diretos
+        let mut uniao = UniaoBusca::new(chaves.len());
+        let mut adjacentes: Vec<Vec<usize>> = vec![Vec::new(); chaves.len()];
+
+        for (a, b) in arestas {
+            uniao.unir(a, b);
+            adjacentes[a].push(b);
+            adjacentes[b].push(a);
+        }
+
+        // O mesmo par pode ter sido informado nos dois sentidos
+        for vizinhos in &mut adjacentes {
+            vizinhos.sort_unstable();
+            vizinhos.dedup();
+        }
+
+        // 3. Numeração compacta dos componentes: raiz -> identificador
+        let mut ids: HashMap<usize, usize> = HashMap::new();
+        let mut componentes = Vec::with_capacity(chaves.len());
+        let mut membros: Vec<Vec<ChaveAcesso>> = Vec::new();
+
+        for (i, &chave) in chaves.iter().enumerate() {
+            let raiz = uniao.raiz(i);
+            let id = *ids.entry(raiz).or_insert_with(|| {
+                membros.push(Vec::new());
+                membros.len() - 1
+            });
+            membros[id].push(chave);
+            componentes.push(id);
+        }
+
+        let nfe_ctes = get_nfe_ctes(&cte_nfes);
+
+        // Relação simétrica: a devolução alcança a venda original e vice-versa
+        let mut referencias = KeyMap::new();
+        for (nfe, refs) in nfe_referencias {
+            for referenciada in refs.into_iter().filter(|&r| r != nfe) {
+                referencias.entry(nfe).or_default().insert(referenciada);
+                referencias.entry(referenciada).or_default().insert(nfe);
+            }
+        }
+
+        IndiceDeRelacoes {
+            posicao: indice,
+            componentes,
+            adjacentes,
+            chaves,
+            membros,
+            cte_nfes,
+            nfe_ctes,
+            referencias,
+        }
+    }
+
+    /// CTes complementares diretos (um salto), nos dois sentidos da relação informada.
+    fn complementares_diretos(&self, cte: &ChaveAcesso) -> impl Iterator<Item = ChaveAcesso> + '_ {
+        self.posicao
+            .get(cte)
+            .into_iter()
+            .flat_map(|&i| &self.adjacentes[i])
+            .map(|&j| self.chaves[j])
+    }
+
+    /// Chaves vizinhas no grafo de relações (relações diretas, sem herança).
+    pub fn vizinhos<'a>(
+        &'a self,
+        chave: &ChaveAcesso,
+    ) -> impl Iterator<Item = (ChaveAcesso, TipoDeRelacao)> + 'a {
+        let transporte = [self.cte_nfes.get(chave), self.nfe_ctes.get(chave)]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|&vizinha| (vizinha, TipoDeRelacao::Transporte));
+
+        let complementar = self
+            .complementares_diretos(chave)
+            .map(|vizinha| (vizinha, TipoDeRelacao::Complementar));
+
+        let referencia =
+            diretas(&self.referencias, chave).map(|vizinha| (vizinha, TipoDeRelacao::Referencia));
+
+        transporte.chain(complementar).chain(referencia)
+    }
+
+    /// Todas as chaves que possuem ao menos uma relação.
+    pub fn chaves_relacionadas(&self) -> impl Iterator<Item = ChaveAcesso> + '_ {
+        self.cte_nfes
+            .keys()
+            .chain(self.nfe_ctes.keys())
+            .chain(&self.chaves)
+            .chain(self.referencias.keys())
+            .copied()
+    }
+
+    /// Identificador do componente de CTes complementares ao qual o CTe pertence.
+    pub fn componente(&self, cte: &ChaveAcesso) -> Option<usize> {
+        self.posicao.get(cte).map(|&i| self.componentes[i])
+    }
+
+    /// Membros do componente (CTe original e CTes complementares).
+    pub fn membros(&self, id: usize) -> &[ChaveAcesso] {
+        self.membros.get(id).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Número de componentes de CTes complementares.
+    pub fn num_componentes(&self) -> usize {
+        self.membros.len()
+    }
+
+    /// CTes do mesmo componente (o próprio CTe, se não houver complementares).
+    pub fn ctes_do_componente<'a>(
+        &'a self,
+        cte: &'a ChaveAcesso,
+    ) -> impl Iterator<Item = ChaveAcesso> + 'a {
+        let membros = match self.componente(cte) {
+            Some(id) => self.membros(id),
+            None => std::slice::from_ref(cte),
+        };
+        membros.iter().copied()
+    }
+
+    /// NFes do CTe, incluindo as herdadas dos demais CTes do componente.
+    pub fn nfes_do_cte<'a>(
+        &'a self,
+        cte: &'a ChaveAcesso,
+    ) -> impl Iterator<Item = ChaveAcesso> + 'a {
+        self.ctes_do_componente(cte)
+            .filter_map(|membro| self.cte_nfes.get(&membro))
+            .flatten()
+            .copied()
+    }
+
+    /// CTes vinculados à NFe, incluindo os complementares dos CTes que a transportam.
+    pub fn ctes_da_nfe<'a>(&'a self, nfe: &ChaveAcesso) -> impl Iterator<Item = ChaveAcesso> + 'a {
+        self.nfe_ctes
+            .get(nfe)
+            .into_iter()
+            .flatten()
+            .flat_map(|cte| self.ctes_do_componente(cte))
+    }
+
+    /// Adiciona ao destino as chaves correlacionadas à chave informada:
+    /// - CTes complementares do mesmo componente;
+    /// - NFes transportadas pelo CTe (inclusive as herdadas no componente);
+    /// - CTes que transportam a NFe (e seus complementares);
+    /// - NFes que referenciam a NFe ou que são referenciadas por ela (um salto).
+    ///
+    /// Cada componente é incluído uma única vez, ainda que muitas chaves da EFD
+    /// pertençam a ele. Prevalece o motivo da primeira inclusão de cada chave.
+    ///
+    /// Apenas as regras c), d) e e) ativas são aplicadas; com `um_salto`, somente os
+    /// vizinhos diretos da chave são incluídos. A própria chave nunca é incluída:
+    /// sem a regra a), um CTe declarado só é retido se for complementar de outra chave.
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::{
+    ///     ChaveAcesso, ComponentesIncluidos, IndiceDeRelacoes, KeyMap, Motivo, Regra,
+    ///     RegrasDeInclusao,
+    /// };
+    /// use std::collections::HashMap;
+    ///
+    /// let a: ChaveAcesso = "35240112345678000190570010000000011000000017".parse().unwrap();
+    /// let b: ChaveAcesso = "35240112345678000190570010000000021000000022".parse().unwrap();
+    ///
+    /// let mut complementar = KeyMap::new();
+    /// complementar.entry(a).or_default().insert(b);
+    ///
+    /// let indice = IndiceDeRelacoes::new(KeyMap::new(), complementar, KeyMap::new());
+    /// let regras = RegrasDeInclusao::new(&[Regra::ChaveDireta], false);
+    /// assert!(!regras.chave_direta);
+    ///
+    /// let mut incluidos = ComponentesIncluidos::default();
+    /// let mut destino = HashMap::new();
+    ///
+    /// // O CTe declarado A não é retido pelo seu próprio componente
+    /// indice.adicionar_correlacionadas(&a, &regras, &mut incluidos, &mut destino);
+    /// assert!(!destino.contains_key(&a));
+    /// assert_eq!(destino.get(&b), Some(&Motivo::Complementar(a)));
+    ///
+    /// // Se B também for declarado, A passa a ser retido como complementar de B
+    /// indice.adicionar_correlacionadas(&b, &regras, &mut incluidos, &mut destino);
+    /// assert_eq!(destino.get(&a), Some(&Motivo::Complementar(b)));
+    /// ```
+    pub fn adicionar_correlacionadas(
+        &self,
+        chave: &ChaveAcesso,
+        regras: &RegrasDeInclusao,
+        incluidos: &mut ComponentesIncluidos,
+        destino: &mut HashMap<ChaveAcesso, Motivo>,
+    ) {
+        let mut adicionar = |chaves: &mut dyn Iterator<Item = ChaveAcesso>, motivo: Motivo| {
+            for correlacionada in chaves {
+                destino.entry(correlacionada).or_insert(motivo);
+            }
+        };
+
+        // Referências entre NFes: sempre relações diretas (devolução -> venda original)
+        if regras.nfe_referenciada {
+            adicionar(
+                &mut diretas(&self.referencias, chave),
+                Motivo::Referenciada(*chave),
+            );
+        }
+
+        if regras.um_salto {
+            if regras.nfe_via_cte {
+                adicionar(
+                    &mut diretas(&self.cte_nfes, chave),
+                    Motivo::NfeDoCte(*chave),
+                );
+                adicionar(
+                    &mut diretas(&self.nfe_ctes, chave),
+                    Motivo::CteDaNfe(*chave),
+                );
+            }
+            if regras.cte_complementar {
+                adicionar(
+                    &mut self.complementares_diretos(chave),
+                    Motivo::Complementar(*chave),
+                );
+            }
+            return;
+        }
+
+        // Sem a regra d), os componentes de CTes complementares são ignorados
+        let componente = |cte| {
+            regras
+                .cte_complementar
+                .then(|| self.componente(cte))
+                .flatten()
+        };
+
+        if let Some(id) = componente(chave) {
+            adicionar(
+                &mut self.membros_pendentes(id, Some(*chave), incluidos),
+                Motivo::Complementar(*chave),
+            );
+        }
+
+        if !regras.nfe_via_cte {
+            return;
+        }
+
+        match componente(chave) {
+            Some(id) => {
+                if incluidos.nfes.insert(id) {
+                    adicionar(&mut self.nfes_do_cte(chave), Motivo::NfeDoCte(*chave));
+                }
+            }
+            None => adicionar(
+                &mut diretas(&self.cte_nfes, chave),
+                Motivo::NfeDoCte(*chave),
+            ),
+        }
+
+        for cte in self.nfe_ctes.get(chave).into_iter().flatten() {
+            match componente(cte) {
+                Some(id) => adicionar(
+                    &mut self.membros_pendentes(id, None, incluidos),
+                    Motivo::CteDaNfe(*chave),
+                ),
+                None => adicionar(&mut std::iter::once(*cte), Motivo::CteDaNfe(*chave)),
+            }
+        }
+    }
+
+    /// Membros do componente ainda não incluídos no filtro.
+    ///
+    /// O CTe declarado que alcança o componente (`propria`) não é complementar de si
+    /// mesmo: sua inclusão cabe à regra a). Ele fica pendente e só é incluído quando
+    /// outra chave alcança o mesmo componente, independentemente da ordem da EFD.
+    fn membros_pendentes<'a>(
+        &'a self,
+        id: usize,
+        propria: Option<ChaveAcesso>,
+        incluidos: &mut ComponentesIncluidos,
+    ) -> Box<dyn Iterator<Item = ChaveAcesso> + 'a> {
+        match incluidos.membros.entry(id) {
+            Entry::Vacant(vaga) => {
+                vaga.insert(propria);
+                Box::new(
+                    self.membros(id)
+                        .iter()
+                        .copied()
+                        .filter(move |&membro| Some(membro) != propria),
+                )
+            }
+            Entry::Occupied(mut ocupada) => {
+                let pendente = ocupada.get().filter(|&p| Some(p) != propria);
+                if pendente.is_some() {
+                    ocupada.insert(None);
+                }
+                Box::new(pendente.into_iter())
+            }
+        }
+    }
+
+    /// Imprime o número de relações e o tamanho do maior componente.
+    pub fn imprimir_estatisticas(&self) {
+        let maior = self.membros.iter().map(Vec::len).max().unwrap_or_default();
+
+        println!(
+            "Índice de relações: {:>6} CTes com NFes, {:>6} NFes com CTes, {:>6} componentes de CTes complementares (maior: {} CTes), {:>6} NFes com referências.\n",
+            fmt_milhares(self.cte_nfes.len()),
+            fmt_milhares(self.nfe_ctes.len()),
+            fmt_milhares(self.num_componentes()),
+            fmt_milhares(maior),
+            fmt_milhares(self.referencias.len()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chave(s: &str) -> ChaveAcesso {
+        s.parse().unwrap()
+    }
+
+    fn cte_a() -> ChaveAcesso {
+        chave("35240112345678000190570010000000011000000017")
+    }
+
+    fn cte_b() -> ChaveAcesso {
+        chave("35240112345678000190570010000000021000000022")
+    }
+
+    fn cte_c() -> ChaveAcesso {
+        chave("35240112345678000190570010000000031000000038")
+    }
+
+    fn nfe() -> ChaveAcesso {
+        chave("35240112345678000190550010000012341123456786")
+    }
+
+    fn mapa(pares: &[(ChaveAcesso, ChaveAcesso)]) -> KeyMap {
+        let mut mapa = KeyMap::new();
+        for &(origem, destino) in pares {
+            mapa.entry(origem).or_default().insert(destino);
+        }
+        mapa
+    }
+
+    /// Cadeia de subcontratação A -> B -> C, com a NFe transportada pelo CTe A.
+    fn cadeia() -> IndiceDeRelacoes {
+        IndiceDeRelacoes::new(
+            mapa(&[(cte_a(), nfe())]),
+            mapa(&[(cte_a(), cte_b()), (cte_b(), cte_c())]),
+            KeyMap::new(),
+        )
+    }
+
+    fn complementares(indice: &IndiceDeRelacoes, cte: &ChaveAcesso) -> Vec<ChaveAcesso> {
+        let mut vizinhos: Vec<_> = indice
+            .vizinhos(cte)
+            .filter(|&(_, tipo)| tipo == TipoDeRelacao::Complementar)
+            .map(|(vizinha, _)| vizinha)
+            .collect();
+        vizinhos.sort_unstable();
+        vizinhos
+    }
+
+    #[test]
+    fn par_informado_nos_dois_sentidos_e_um_unico_vizinho() {
+        let indice = IndiceDeRelacoes::new(
+            KeyMap::new(),
+            mapa(&[(cte_a(), cte_b()), (cte_b(), cte_a())]),
+            KeyMap::new(),
+        );
+
+        assert_eq!(indice.num_componentes(), 1);
+        assert_eq!(complementares(&indice, &cte_a()), vec![cte_b()]);
+        assert_eq!(complementares(&indice, &cte_b()), vec![cte_a()]);
+    }
+
+    #[test]
+    fn vizinhos_diretos_nao_incluem_o_fecho_transitivo() {
+        let indice = cadeia();
+
+        assert_eq!(complementares(&indice, &cte_a()), vec![cte_b()]);
+        assert_eq!(complementares(&indice, &cte_b()), vec![cte_a(), cte_c()]);
+        assert_eq!(complementares(&indice, &cte_c()), vec![cte_b()]);
+        assert_eq!(indice.membros(0).len(), 3);
+        assert_eq!(
+            indice
+                .chaves_relacionadas()
+                .filter(|&c| c == cte_c())
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn componentes_disjuntos_nao_compartilham_nfes() {
+        let d = chave("35240112345678000190570010000000041000000043");
+        let e = chave("35240112345678000190570010000000051000000049");
+
+        let mut complementar = mapa(&[(cte_a(), cte_b()), (d, e)]);
+        complementar.entry(cte_c()).or_default().insert(cte_c());
+
+        let indice = IndiceDeRelacoes::new(mapa(&[(cte_a(), nfe())]), complementar, KeyMap::new());
+
+        // A auto-referência não cria componente
+        assert_eq!(indice.num_componentes(), 2);
+        assert_eq!(indice.componente(&cte_c()), None);
+        assert_ne!(indice.componente(&cte_a()), indice.componente(&d));
+
+        assert!(indice.nfes_do_cte(&cte_b()).any(|n| n == nfe()));
+        assert_eq!(indice.nfes_do_cte(&e).count(), 0);
+        assert_eq!(
+            indice.ctes_da_nfe(&nfe()).collect::<HashSet<_>>(),
+            HashSet::from([cte_a(), cte_b()])
+        );
+    }
+
+    #[test]
+    fn um_salto_inclui_apenas_os_vizinhos_diretos() {
+        let indice = cadeia();
+        let regras = RegrasDeInclusao::new(&[], true);
+
+        let mut destino = HashMap::new();
+        indice.adicionar_correlacionadas(
+            &cte_c(),
+            &regras,
+            &mut ComponentesIncluidos::default(),
+            &mut destino,
+        );
+
+        // A NFe é transportada pelo CTe A: sem o fecho, o CTe C não a alcança
+        assert_eq!(
+            destino,
+            HashMap::from([(cte_b(), Motivo::Complementar(cte_c()))])
+        );
+    }
+
+    #[test]
+    fn componente_e_incluido_uma_unica_vez() {
+        let indice = cadeia();
+        let regras = RegrasDeInclusao::default();
+        let mut incluidos = ComponentesIncluidos::default();
+        let mut destino = HashMap::new();
+
+        indice.adicionar_correlacionadas(&cte_c(), &regras, &mut incluidos, &mut destino);
+        assert_eq!(destino.get(&cte_a()), Some(&Motivo::Complementar(cte_c())));
+        assert_eq!(destino.get(&cte_b()), Some(&Motivo::Complementar(cte_c())));
+        assert_eq!(destino.get(&nfe()), Some(&Motivo::NfeDoCte(cte_c())));
+        assert!(!destino.contains_key(&cte_c()));
+
+        // O CTe C pendente é incluído quando outra chave alcança o componente
+        indice.adicionar_correlacionadas(&nfe(), &regras, &mut incluidos, &mut destino);
+        assert_eq!(destino.get(&cte_c()), Some(&Motivo::CteDaNfe(nfe())));
+
+        let num_chaves = destino.len();
+        indice.adicionar_correlacionadas(&cte_b(), &regras, &mut incluidos, &mut destino);
+        assert_eq!(destino.len(), num_chaves);
+    }
+
+    #[test]
+    fn referencias_entre_nfes_sao_simetricas() {
+        let devolucao = chave("35240212345678000190550010000056781123456780");
+        let indice = IndiceDeRelacoes::new(
+            KeyMap::new(),
+            KeyMap::new(),
+            mapa(&[(devolucao, nfe()), (devolucao, devolucao)]),
+        );
+
+        let vizinhos = |chave| indice.vizinhos(&chave).collect::<Vec<_>>();
+
+        assert_eq!(
+            vizinhos(nfe()),
+            vec![(devolucao, TipoDeRelacao::Referencia)]
+        );
+        assert_eq!(
+            vizinhos(devolucao),
+            vec![(nfe(), TipoDeRelacao::Referencia)]
+        );
+    }
+}
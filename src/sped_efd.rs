@@ -1,18 +1,21 @@
+use clap::ValueEnum;
 use rayon::prelude::*;
-use regex::Regex;
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
-    fmt::Display,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fs::{self, File},
     io::{BufRead, BufReader, BufWriter, Write},
     path::{Path, PathBuf},
     process::Command,
-    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use crate::{
-    Config, RE_CHAVE_44, RE_MULTISPACE, RE_NON_DIGITS, SpedError, SpedResult,
-    get_modelo_documentos_fiscais,
+    AnoMes, COLUNAS_EFD, COLUNAS_EFD_ICMS_IPI, ChaveAcesso, ChaveInvalida, Codificacao,
+    ComponentesIncluidos, Config, DocumentoSemChave, FiltroEfd, InconsistenciaDePeriodo,
+    ItemConciliado, LeitorDecodificado, LinhaRejeitada, Modelo, Motivo, MotivoDeRejeicao,
+    RE_CHAVES_NA_LINHA, RE_MULTISPACE, SpedError, SpedResult, VALORES_CONCILIADOS_CONTRIBUICOES,
+    VALORES_CONCILIADOS_ICMS_IPI, Valor, ValorConciliado, ValoresDoItem, abrir_decodificado,
+    acumular_item, eh_cancelada, get_modelo_documentos_fiscais, imprimir_linhas_rejeitadas,
+    numero_do_item, process_xml_nfe_dir, verificar_periodo,
 };
 
 /// Limpar a tela.
@@ -60,61 +63,22 @@ pub fn imprimir_versao_do_programa() {
 }
 
 /// Tipo alias para representar o mapa de relações entre chaves de CTe.
-pub type KeyMap = HashMap<String, HashSet<String>>;
+pub type KeyMap = HashMap<ChaveAcesso, HashSet<ChaveAcesso>>;
 
-/// Verifica se a chave tem 44 caracteres e se o modelo (posições 21-22) coincide.
-fn eh_modelo(chave: &str, modelo: &str) -> bool {
-    chave.len() == 44 && chave.get(20..22) == Some(modelo)
-}
+/// Relações lidas de um arquivo de texto: chaves relacionadas, ocorrências de chaves
+/// com DV inválido e linhas descartadas.
+pub type RelacoesLidas = (KeyMap, Vec<ChaveInvalida>, Vec<LinhaRejeitada>);
 
-pub fn ler_todas_as_nfes_deste_cte<P>(path: P) -> SpedResult<KeyMap>
+pub fn ler_todas_as_nfes_deste_cte<P>(
+    path: P,
+    codificacao: Codificacao,
+) -> SpedResult<RelacoesLidas>
 where
-    P: AsRef<Path> + Clone + Display,
+    P: AsRef<Path>,
 {
-    let file = File::open(&path).map_err(|e| SpedError::IoReader {
-        source: e,
-        arquivo: path.clone().as_ref().to_path_buf(),
-    })?;
-
-    let reader = BufReader::new(file);
-
-    // Compila o regex apenas uma vez.
-    // \b garante que pegamos apenas sequências de 44 dígitos isoladas.
-    let re = Regex::new(r"\b\d{44}\b")?;
-
-    let hash: KeyMap = reader
-        .lines()
-        .par_bridge() // Transforma o iterador sequencial em paralelo
-        .map(
-            |line_result| -> SpedResult<Option<(String, HashSet<String>)>> {
-                // Se houver erro de leitura na linha, o '?' propaga o SpedError::Io
-                let line = line_result?;
-                let mut chaves = re.find_iter(&line).map(|m| m.as_str());
-
-                // O primeiro match deve ser o CT-e (modelo 57)
-                match chaves.next() {
-                    Some(cte) if eh_modelo(cte, "57") => {
-                        // Os demais matches são as NFes (modelo 55)
-                        let nfes: HashSet<String> = chaves
-                            .filter(|nfe| eh_modelo(nfe, "55"))
-                            .map(String::from)
-                            .collect();
-
-                        if nfes.is_empty() {
-                            Ok(None)
-                        } else {
-                            Ok(Some((cte.to_string(), nfes)))
-                        }
-                    }
-                    _ => Ok(None), // Não é uma linha de CT-e válida
-                }
-            },
-        )
-        // O truque mágico: transforma Result<Option<T>, E> em Option<Result<T, E>>
-        // O filter_map remove os Nones, mas mantém os Errs
-        .filter_map(|res| res.transpose())
-        // O collect em um Result<Map, Error> interrompe no primeiro erro encontrado
-        .collect::<SpedResult<KeyMap>>()?;
+    let arquivo = path.as_ref().to_path_buf();
+    let (hash, invalidas, rejeitadas) =
+        ler_arquivo_de_relacoes(&arquivo, codificacao, validar_cte_nfes)?;
 
     // Estatísticas usando funcional
     let num_cte = hash.len();
@@ -124,51 +88,271 @@ where
         "Encontrado {:>6} CTes contendo no total {:>6} NFes no arquivo <{}>.",
         fmt_milhares(num_cte),
         fmt_milhares(num_nfe),
-        path
+        arquivo.display()
     );
+    imprimir_linhas_rejeitadas(&rejeitadas, &arquivo);
 
-    Ok(hash)
+    Ok((hash, invalidas, rejeitadas))
+}
+
+pub fn ler_chave_complementar_deste_cte<P>(
+    path: P,
+    codificacao: Codificacao,
+) -> SpedResult<RelacoesLidas>
+where
+    P: AsRef<Path>,
+{
+    let arquivo = path.as_ref().to_path_buf();
+    let (hash, invalidas, rejeitadas) =
+        ler_arquivo_de_relacoes(&arquivo, codificacao, validar_cte_complementar)?;
+
+    let num_cte = hash.len();
+    let num_com = hash.values().map(|v| v.len()).sum::<usize>();
+
+    println!(
+        "Encontrado {:>6} CTes contendo no total {:>6} CTes Complementares no arquivo <{}>.",
+        fmt_milhares(num_cte),
+        fmt_milhares(num_com),
+        arquivo.display()
+    );
+    imprimir_linhas_rejeitadas(&rejeitadas, &arquivo);
+
+    Ok((hash, invalidas, rejeitadas))
 }
 
-pub fn ler_chave_complementar_deste_cte<P>(path: P) -> SpedResult<KeyMap>
+/// Arquivo de referências entre NFes (`refNFe`): cada linha contém a chave da NFe
+/// (ex: devolução) seguida das chaves das NFes referenciadas (ex: venda original).
+pub fn ler_nfes_referenciadas_desta_nfe<P>(
+    path: P,
+    codificacao: Codificacao,
+) -> SpedResult<RelacoesLidas>
 where
-    P: AsRef<Path> + Clone + Display,
+    P: AsRef<Path>,
 {
-    let file = File::open(&path).map_err(|e| SpedError::IoReader {
-        source: e,
-        arquivo: path.clone().as_ref().to_path_buf(),
-    })?;
+    let arquivo = path.as_ref().to_path_buf();
+    let (hash, invalidas, rejeitadas) =
+        ler_arquivo_de_relacoes(&arquivo, codificacao, validar_nfe_referenciadas)?;
 
-    let reader = BufReader::new(file);
-    let re = Regex::new(r"\b\d{44}\b")?;
+    let num_nfe = hash.len();
+    let num_ref = hash.values().map(|v| v.len()).sum::<usize>();
+
+    println!(
+        "Encontrado {:>6} NFes contendo no total {:>6} NFes Referenciadas no arquivo <{}>.",
+        fmt_milhares(num_nfe),
+        fmt_milhares(num_ref),
+        arquivo.display()
+    );
+    imprimir_linhas_rejeitadas(&rejeitadas, &arquivo);
+
+    Ok((hash, invalidas, rejeitadas))
+}
+
+/// Leitura paralela de um arquivo de relações: cada linha é validada por `validar`,
+/// que devolve os pares de chaves relacionadas ou o motivo do descarte da linha.
+fn ler_arquivo_de_relacoes<F>(
+    arquivo: &Path,
+    codificacao: Codificacao,
+    validar: F,
+) -> SpedResult<RelacoesLidas>
+where
+    F: Fn(&[ChaveAcesso]) -> Result<Vec<(ChaveAcesso, ChaveAcesso)>, MotivoDeRejeicao> + Sync,
+{
+    let reader = BufReader::new(abrir_decodificado(arquivo, codificacao)?);
 
     // No Rayon, try_fold e try_reduce trabalham juntos para processar e mesclar resultados
-    let hash: KeyMap = reader
+    reader
         .lines()
+        .enumerate()
         .par_bridge() // Transforma o iterador sequencial em paralelo
         .try_fold(
-            HashMap::new, // Criador de acumulador local para cada thread
-            |mut acc: KeyMap, line_result| -> SpedResult<KeyMap> {
+            RelacoesLidas::default, // Acumulador local para cada thread
+            |(mut acc, mut invalidas, mut rejeitadas), (idx, line_result)| -> SpedResult<_> {
+                // Se houver erro de leitura na linha, o '?' propaga o SpedError::Io
                 let line = line_result?;
-                let mut matches = re.find_iter(&line).map(|m| m.as_str());
-
-                if let (Some(cte), Some(comp)) = (matches.next(), matches.next()) {
-                    // Validação: Ambos modelo 57 e chaves diferentes
-                    if eh_modelo(cte, "57") && eh_modelo(comp, "57") && cte != comp {
-                        // Inserção bidirecional
-                        acc.entry(cte.to_string())
-                            .or_default()
-                            .insert(comp.to_string());
-                        acc.entry(comp.to_string())
-                            .or_default()
-                            .insert(cte.to_string());
+
+                // Linhas em branco não são consideradas rejeitadas
+                if line.trim().is_empty() {
+                    return Ok((acc, invalidas, rejeitadas));
+                }
+
+                let chaves = extrair_chaves_da_linha(&line);
+                invalidas.extend(registrar_chaves_invalidas(&chaves, arquivo, idx + 1));
+
+                match validar(&chaves) {
+                    Ok(pares) => {
+                        for (a, b) in pares {
+                            acc.entry(a).or_default().insert(b);
+                        }
                     }
+                    Err(motivo) => rejeitadas.push(LinhaRejeitada {
+                        arquivo: arquivo.to_path_buf(),
+                        linha: idx + 1,
+                        motivo,
+                        conteudo: line,
+                    }),
                 }
-                Ok(acc)
+
+                Ok((acc, invalidas, rejeitadas))
             },
         )
         // try_reduce mescla os mapas parciais gerados pelas threads
-        .try_reduce(HashMap::new, |mut map_a, map_b| {
+        .try_reduce(
+            RelacoesLidas::default,
+            |(mut map_a, mut inv_a, mut rej_a), (map_b, inv_b, rej_b)| {
+                for (key, values) in map_b {
+                    map_a.entry(key).or_default().extend(values);
+                }
+                inv_a.extend(inv_b);
+                rej_a.extend(rej_b);
+                Ok((map_a, inv_a, rej_a))
+            },
+        )
+}
+
+/// Linha do arquivo `cte_nfes`: chave do CTe seguida das chaves das NFes.
+fn validar_cte_nfes(
+    chaves: &[ChaveAcesso],
+) -> Result<Vec<(ChaveAcesso, ChaveAcesso)>, MotivoDeRejeicao> {
+    // O primeiro match deve ser o CT-e (modelo 57) com DV válido
+    let Some((cte, demais)) = chaves.split_first() else {
+        return Err(MotivoDeRejeicao::SemChaves);
+    };
+
+    if !cte.eh_modelo("57") {
+        return Err(MotivoDeRejeicao::PrimeiraChaveNaoCte);
+    }
+
+    if !cte.dv_valido() {
+        return Err(MotivoDeRejeicao::DvInvalido);
+    }
+
+    // Os demais matches são as NFes (modelo 55)
+    let pares: Vec<(ChaveAcesso, ChaveAcesso)> = demais
+        .iter()
+        .filter(|nfe| nfe.eh_modelo("55") && nfe.dv_valido())
+        .map(|&nfe| (*cte, nfe))
+        .collect();
+
+    if pares.is_empty() {
+        return Err(MotivoDeRejeicao::SemNfes);
+    }
+
+    Ok(pares)
+}
+
+/// Linha do arquivo de NFes referenciadas: chave da NFe seguida das NFes referenciadas.
+fn validar_nfe_referenciadas(
+    chaves: &[ChaveAcesso],
+) -> Result<Vec<(ChaveAcesso, ChaveAcesso)>, MotivoDeRejeicao> {
+    let Some((nfe, demais)) = chaves.split_first() else {
+        return Err(MotivoDeRejeicao::SemChaves);
+    };
+
+    if !nfe.eh_modelo("55") {
+        return Err(MotivoDeRejeicao::PrimeiraChaveNaoNfe);
+    }
+
+    if !nfe.dv_valido() {
+        return Err(MotivoDeRejeicao::DvInvalido);
+    }
+
+    let pares: Vec<(ChaveAcesso, ChaveAcesso)> = demais
+        .iter()
+        .filter(|r| r.eh_modelo("55") && r.dv_valido() && *r != nfe)
+        .map(|&referenciada| (*nfe, referenciada))
+        .collect();
+
+    if pares.is_empty() {
+        return Err(MotivoDeRejeicao::SemNfes);
+    }
+
+    Ok(pares)
+}
+
+/// Linha do arquivo de CTes complementares: chave do CTe seguida da chave do complementar.
+fn validar_cte_complementar(
+    chaves: &[ChaveAcesso],
+) -> Result<Vec<(ChaveAcesso, ChaveAcesso)>, MotivoDeRejeicao> {
+    let [cte, comp, ..] = chaves[..] else {
+        return Err(match chaves {
+            [] => MotivoDeRejeicao::SemChaves,
+            _ => MotivoDeRejeicao::MenosDeDuasChaves,
+        });
+    };
+
+    // Validação: Ambos modelo 57, DVs válidos e chaves diferentes
+    if !cte.eh_modelo("57") || !comp.eh_modelo("57") {
+        return Err(MotivoDeRejeicao::ChaveNaoCte);
+    }
+
+    if !cte.dv_valido() || !comp.dv_valido() {
+        return Err(MotivoDeRejeicao::DvInvalido);
+    }
+
+    if cte == comp {
+        return Err(MotivoDeRejeicao::AutoReferencia);
+    }
+
+    // Cada par é armazenado uma única vez: o sentido inverso é obtido do `IndiceDeRelacoes`
+    Ok(vec![(cte, comp)])
+}
+
+/// Obtém pares CTe -> NFe das colunas `chave44_digitos` (CTe) e `chave_de_acesso` (NFe)
+/// dos arquivos CSV de Documentos Fiscais.
+///
+/// Primeira leitura (opcional) dos Documentos Fiscais: as relações obtidas são mescladas
+/// em `cte_nfes` antes da construção do `IndiceDeRelacoes`, dispensando o arquivo
+/// `cte_nfes.txt` nos casos em que o ReceitaNet-BX informa a NFe transportada ao lado
+/// da chave do CTe.
+pub fn ler_cte_nfes_dos_documentos(config: &Config) -> SpedResult<KeyMap> {
+    let hash = config
+        .arquivos_csv
+        .par_iter()
+        .filter(|path| path.is_file())
+        .map(|path| -> SpedResult<KeyMap> {
+            let file = abrir_decodificado(path, config.codificacao)?;
+            let mut rdr = csv::ReaderBuilder::new()
+                .delimiter(b';')
+                .has_headers(true)
+                .flexible(false)
+                .trim(csv::Trim::All)
+                .buffer_capacity(128 * 1024)
+                .from_reader(BufReader::new(file));
+
+            let column_names: Vec<&str> = rdr.headers()?.iter().collect();
+            let localizar = |campo| {
+                localizar_coluna(
+                    &column_names,
+                    campo,
+                    TipoDeArquivo::DocFiscais,
+                    config,
+                    path,
+                )
+            };
+
+            let idx_cte = localizar("chave44_digitos")?;
+            let idx_nfe = localizar("chave_de_acesso")?;
+
+            let mut acc = KeyMap::new();
+            let mut record = csv::StringRecord::new();
+
+            while rdr.read_record(&mut record)? {
+                let chave = |i: usize| {
+                    limpar_chave(record.get(i).unwrap_or_default()).filter(ChaveAcesso::dv_valido)
+                };
+
+                if let (Some(cte), Some(nfe)) = (chave(idx_cte), chave(idx_nfe))
+                    && cte.eh_modelo("57")
+                    && nfe.eh_modelo("55")
+                {
+                    acc.entry(cte).or_default().insert(nfe);
+                }
+            }
+
+            Ok(acc)
+        })
+        // try_reduce mescla os mapas parciais de cada arquivo
+        .try_reduce(KeyMap::new, |mut map_a, map_b| {
             for (key, values) in map_b {
                 map_a.entry(key).or_default().extend(values);
             }
@@ -176,174 +360,129 @@ where
         })?;
 
     let num_cte = hash.len();
-    let num_com = hash.values().map(|v| v.len()).sum::<usize>();
+    let num_nfe = hash.values().map(|v| v.len()).sum::<usize>();
 
     println!(
-        "Encontrado {:>6} CTes contendo no total {:>6} CTes Complementares no arquivo <{}>.",
+        "Encontrado {:>6} CTes contendo no total {:>6} NFes nos arquivos de Documentos Fiscais.",
         fmt_milhares(num_cte),
-        fmt_milhares(num_com),
-        path
+        fmt_milhares(num_nfe),
     );
 
     Ok(hash)
 }
 
-pub fn get_nfe_ctes(cte_nfes: &KeyMap) -> KeyMap {
-    let mut nfe_ctes: KeyMap = HashMap::new();
-    for (cte, nfes) in cte_nfes {
-        for nfe in nfes {
-            nfe_ctes.entry(nfe.clone()).or_default().insert(cte.clone());
+/// Carrega um arquivo de relações entre chaves (CTe -> NFes ou CTe -> CTe complementar).
+///
+/// O arquivo é opcional: se desativado (`None`) ou inexistente, a auditoria prossegue
+/// sem estas relações, apenas com as chaves declaradas na EFD. O arquivo inexistente
+/// é informado em um aviso (os arquivos padrão ausentes já chegam como `None`).
+pub fn carregar_relacoes<'a, F>(path: Option<&'a Path>, ler: F) -> SpedResult<RelacoesLidas>
+where
+    F: FnOnce(&'a Path) -> SpedResult<RelacoesLidas>,
+{
+    match path {
+        Some(path) if path.is_file() => ler(path),
+        Some(path) => {
+            eprintln!(
+                " [AVISO] Arquivo de relações <{}> não encontrado. Prosseguindo sem estas relações.",
+                path.display()
+            );
+            Ok(RelacoesLidas::default())
         }
+        None => Ok(RelacoesLidas::default()),
     }
-    nfe_ctes
 }
 
-/// Expande as relações de transitividade entre CTes Complementares.
-///
-/// Esta função resolve o problema de encontrar "componentes conectados" em um grafo de documentos.
-/// Se um CTe **A** referencia **B**, e **B** referencia **C**, a função entende que todos
-/// pertencem ao mesmo grupo e atualiza o mapa para que todos apontem para todos.
-///
-/// ### Lógica de Negócio (Transitividade)
-/// Em termos práticos, se houver uma cadeia de complementos (A -> B -> C), o algoritmo
-/// garante que o resultado final contenha:
-/// - A conhece {B, C}
-/// - B conhece {A, C}
-/// - C conhece {A, B}
-///
-/// ### Algoritmo
-/// O processo é realizado em três etapas principais:
-/// 1. **Simetrização**: Garante que se A aponta para B, B também aponte para A no grafo inicial.
-/// 2. **Busca de Componentes**: Utiliza uma Busca em Profundidade (DFS) para agrupar todos os
-///    CTes que possuem qualquer ligação entre si (direta ou indireta).
-/// 3. **Clique (Expansão Total)**: Para cada grupo encontrado, reconstrói o mapa original
-///    onde cada membro do grupo possui como vizinhos todos os outros integrantes.
-///
-/// ### Performance
-/// Esta implementação utiliza a identificação de componentes conectados,
-/// resultando em uma complexidade **O(V + E)**, onde:
-/// - **V** é o número de chaves (vértices).
-/// - **E** é o número de relações (arestas).
-///
-/// ### Exemplo
-/// ```
-/// use reter_linhas_com_info_das_chaves::{expand_cte_complementar, KeyMap};
-/// use std::collections::HashMap;
-///
-/// let mut mapa: KeyMap = HashMap::new();
-/// mapa.entry("A".to_string()).or_default().insert("B".to_string());
-/// mapa.entry("B".to_string()).or_default().insert("C".to_string());
+/// Extrai, na ordem em que aparecem, as chaves de 44 posições de uma linha.
 ///
-/// expand_cte_complementar(&mut mapa);
-///
-/// assert!(mapa.get("A").unwrap().contains("C"));
-/// assert!(mapa.get("C").unwrap().contains("A"));
-/// ```
-pub fn expand_cte_complementar(map: &mut KeyMap) {
-    // 1. Criar um grafo de adjacência simétrico para garantir bidirecionalidade
-    let mut adj: HashMap<String, HashSet<String>> = HashMap::new();
-    for (u, neighbors) in map.drain() {
-        for v in neighbors {
-            adj.entry(u.clone()).or_default().insert(v.clone());
-            adj.entry(v).or_default().insert(u.clone());
-        }
-    }
-
-    let mut visited = HashSet::new();
-    let keys: Vec<String> = adj.keys().cloned().collect();
-
-    for node in keys {
-        if visited.contains(&node) {
-            continue;
-        }
-
-        // 2. Identificar todos os membros da "ilha" (componente conectado) via DFS
-        let mut group = Vec::new();
-        let mut stack = vec![node];
-
-        while let Some(current) = stack.pop() {
-            if visited.insert(current.clone()) {
-                group.push(current.clone());
-                if let Some(neighbors) = adj.get(&current) {
-                    stack.extend(neighbors.iter().cloned());
-                }
-            }
-        }
+/// O regex é compilado apenas uma vez (LazyLock) e aceita o CNPJ alfanumérico.
+/// A linha é convertida para maiúsculas, como as chaves da EFD e dos Documentos Fiscais:
+/// chaves com CNPJ alfanumérico escritas em minúsculas não são descartadas.
+fn extrair_chaves_da_linha(line: &str) -> Vec<ChaveAcesso> {
+    RE_CHAVES_NA_LINHA
+        .find_iter(&line.to_ascii_uppercase())
+        .filter_map(|m| m.as_str().parse::<ChaveAcesso>().ok())
+        .collect()
+}
 
-        // 3. Criar a relação "todos com todos" (clique) para este grupo
-        for member in &group {
-            let mut others: HashSet<String> = group.iter().cloned().collect();
-            others.remove(member); // Um CTe não é complementar de si mesmo
+/// Seleciona as chaves com DV inválido, registrando a origem (arquivo e linha).
+fn registrar_chaves_invalidas(
+    chaves: &[ChaveAcesso],
+    arquivo: &Path,
+    linha: usize,
+) -> Vec<ChaveInvalida> {
+    chaves
+        .iter()
+        .filter(|chave| !chave.dv_valido())
+        .map(|&chave| ChaveInvalida {
+            chave,
+            arquivo: arquivo.to_path_buf(),
+            linha,
+        })
+        .collect()
+}
 
-            if !others.is_empty() {
-                map.insert(member.clone(), others);
-            }
+pub fn get_nfe_ctes(cte_nfes: &KeyMap) -> KeyMap {
+    let mut nfe_ctes: KeyMap = HashMap::new();
+    for (cte, nfes) in cte_nfes {
+        for nfe in nfes {
+            nfe_ctes.entry(*nfe).or_default().insert(*cte);
         }
     }
+    nfe_ctes
 }
 
-/// Expande a associação de NFEs para CTes complementares.
-///
-/// ### Lógica de Negócio
-/// No transporte de cargas (SPED), um CTe Complementar herda as notas fiscais (NFEs)
-/// do seu CTe de referência. Esta função garante que se o **CTe A** possui as
-/// **Notas 1 e 2**, e o **CTe B** é complementar de **A**, então **B** também
-/// passará a listar as **Notas 1 e 2**.
-///
-/// ### Otimização de Performance
-/// Diferente da abordagem com `Vec<(String, String)>`, esta versão:
-/// 1. Usa um `HashMap<String, HashSet<String>>` temporário para agrupar notas por CTe.
-/// 2. Reduz a pressão sobre o alocador de memória ao evitar a criação de milhões de tuplas.
-/// 3. Utiliza `extend` para mesclar conjuntos de dados de uma só vez, o que é mais
-///    eficiente em Rust do que inserções individuais em loops.
-///
-/// Se um CTe de origem existe em ambos os mapas,
-/// todos os seus "alvos" complementares recebem todas as suas NFEs.
-///
-/// ### Exemplo
-/// ```
-/// // Se CTe "123" tem NFEs {"Nota_A"}
-/// // E CTe "456" é complementar de "123"
-/// // Após a função, CTe "456" terá {"Nota_A"} em suas notas.
-/// ```
-pub fn expand_cte_nfes(cte_nfes: &mut KeyMap, cte_complementar: &KeyMap) {
-    // 1. Acumulador temporário para evitar conflitos de empréstimo (borrow checker)
-    // e reduzir a duplicidade de chaves durante o processamento.
-    let mut updates: HashMap<String, HashSet<String>> = HashMap::new();
-
-    // 2. Itera sobre os CTes que possuem NFEs
-    for (cte, nfes) in cte_nfes.iter() {
-        // Se este CTe possui complementares associados...
-        if let Some(complements) = cte_complementar.get(cte) {
-            for comp in complements {
-                // Adiciona todas as NFEs do CTe pai ao CTe complementar no acumulador
-                updates
-                    .entry(comp.clone())
-                    .or_default()
-                    .extend(nfes.iter().cloned());
-            }
-        }
-    }
+/// Resultado da leitura da EFD Contribuições.
+#[derive(Debug, Default)]
+pub struct InfoEfd {
+    /// Chaves declaradas na EFD e chaves correlacionadas (CTes/NFes).
+    pub chaves: HashSet<ChaveAcesso>,
+    /// Chaves declaradas diretamente na EFD (sem as correlacionadas).
+    pub declaradas: HashSet<ChaveAcesso>,
+    /// Chaves correlacionadas (CTes/NFes) e o motivo da sua inclusão no filtro.
+    pub motivos: HashMap<ChaveAcesso, Motivo>,
+    /// Documentos sem chave (preenchido apenas com `--sem-chave`).
+    pub documentos_sem_chave: HashSet<DocumentoSemChave>,
+    /// Modelos dos documentos sem chave, procurados nas linhas sem chave dos Documentos Fiscais.
+    pub modelos_sem_chave: BTreeSet<String>,
+    /// Ocorrências de chaves com DV inválido.
+    pub invalidas: Vec<ChaveInvalida>,
+    /// Linhas com AAMM da chave inconsistente (preenchido apenas com `--verificar-periodos`).
+    pub inconsistencias_de_periodo: Vec<InconsistenciaDePeriodo>,
+    /// Arquivos da EFD e períodos de apuração em que cada chave do filtro foi declarada
+    /// (as correlacionadas herdam a origem da chave declarada).
+    pub origens: HashMap<ChaveAcesso, BTreeSet<OrigemEfd>>,
+    /// Número de linhas lidas dos arquivos da EFD.
+    pub linhas_lidas: usize,
+    /// Linhas excluídas pelos filtros da EFD (cada linha contada no primeiro filtro que a exclui).
+    pub linhas_excluidas: BTreeMap<FiltroEfd, usize>,
+    /// Valores das chaves declaradas, somados por chave e item (apenas com `--conciliar`).
+    /// Os valores de cada chave provêm de um único arquivo da EFD (o último informado).
+    pub valores: HashMap<ItemConciliado, ValoresDoItem>,
+}
 
-    // 3. Mescla os novos dados acumulados de volta no mapa original.
-    // O uso de 'extend' em um HashSet é otimizado internamente.
-    for (target_cte, new_nfes) in updates {
-        cte_nfes.entry(target_cte).or_default().extend(new_nfes);
-    }
+/// Arquivo da EFD e período de apuração de origem de uma chave.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OrigemEfd {
+    pub arquivo: PathBuf,
+    pub periodo_apuracao: String,
+    /// Motivo da inclusão da chave a partir deste arquivo e período.
+    pub motivo: Motivo,
 }
 
-pub fn get_efd_info(config: &Config) -> SpedResult<HashSet<String>> {
+/// Abre o arquivo CSV da EFD (delimitador '|'), transcodificado para UTF-8.
+pub fn leitor_efd(
+    efd_path: &Path,
+    codificacao: Codificacao,
+) -> SpedResult<csv::Reader<BufReader<LeitorDecodificado<File>>>> {
     // 1. Definir delimitador '|'
     let delimiter = b'|';
 
     // 2. Configuração do Reader (Encapsulada para clareza)
-    let file = File::open(&config.efd_path).map_err(|e| SpedError::IoReader {
-        source: e,
-        arquivo: config.efd_path.clone(),
-    })?;
+    let file = abrir_decodificado(efd_path, codificacao)?;
 
     // 3. Abertura eficiente do arquivo com BufReader aumentado para 128KB
-    let mut rdr = csv::ReaderBuilder::new()
+    let rdr = csv::ReaderBuilder::new()
         .delimiter(delimiter)
         .has_headers(true) // O crate gerencia o cabeçalho automaticamente
         .flexible(false) // Garante integridade (erro se o num de colunas variar)
@@ -351,78 +490,337 @@ pub fn get_efd_info(config: &Config) -> SpedResult<HashSet<String>> {
         .buffer_capacity(128 * 1024)
         .from_reader(BufReader::new(file));
 
+    Ok(rdr)
+}
+
+/// Lê os arquivos da EFD Contribuições, reunindo as chaves de todos os arquivos.
+pub fn get_efd_info(config: &Config) -> SpedResult<InfoEfd> {
+    let mut info = InfoEfd::default();
+
+    for arquivo in &config.arquivos_efd {
+        ler_arquivo_efd(config, arquivo, &mut info)?;
+    }
+
+    // O filtro contém as chaves declaradas e as correlacionadas
+    info.chaves.extend(info.motivos.keys());
+
+    Ok(info)
+}
+
+fn ler_arquivo_efd(config: &Config, arquivo: &ArquivoEfd, info: &mut InfoEfd) -> SpedResult<()> {
+    let efd_path = arquivo.path.as_path();
+    let mut rdr = leitor_efd(efd_path, arquivo.codificacao)?;
+
     // 4. Obtenção dos nomes das colunas
     let column_names: Vec<&str> = rdr.headers()?.iter().collect();
 
     // 5. Validação centralizada (verifica se as colunas do config existem no arquivo)
     verificar_existencia_de_colunas_essenciais(
         &column_names,
-        TipoDeArquivo::EFDContrib,
+        arquivo.tipo,
         config,
-        config.efd_path.clone(),
+        efd_path.to_path_buf(),
     )?;
 
-    // 6. Localização da coluna alvo (Chave de 44 dígitos)
-    let target_col_name = config
-        .colunas_efd
-        .get("chave_documento")
-        .ok_or_else(|| SpedError::Config("Configuração 'chave_documento' ausente".into()))?;
+    // 6. Localização das colunas alvo (Chave de 44 dígitos e identificação sem chave)
+    let localizar = |campo| localizar_coluna(&column_names, campo, arquivo.tipo, config, efd_path);
+
+    let idx_chave = localizar("chave_documento")?;
+    let idx_periodo = localizar("periodo_apuracao")?;
+
+    // Colunas do Nº da Linha da EFD e das datas (apenas com `--verificar-periodos`)
+    let idx_periodos = if config.verificar_periodos {
+        Some([
+            localizar("efd_line")?,
+            localizar("data_emissao_nota")?,
+            localizar("data_lancamento")?,
+        ])
+    } else {
+        None
+    };
 
-    // 7. Encontrar a posição da coluna no cabeçalho
-    let idx_chave = column_names
-        .iter()
-        .position(|col| col == target_col_name)
-        .ok_or_else(|| SpedError::MissingEssentialColumn {
-            arquivo: config.efd_path.clone(),
-            coluna: target_col_name.to_string(),
-            tipo: TipoDeArquivo::EFDContrib,
-        })?;
+    // Colunas da identificação sem chave: CNPJ, modelo e número (apenas com `--sem-chave`)
+    let idx_sem_chave = if config.sem_chave {
+        Some([
+            localizar("cnpj_participante")?,
+            localizar("modelo_doc_fiscal")?,
+            localizar("num_doc_fiscal")?,
+        ])
+    } else {
+        None
+    };
+
+    // Colunas de valores e do número do item (apenas com `--conciliar`)
+    let idx_conciliacao = match config.conciliacao {
+        Some(_) => Some((
+            config
+                .escrituracao
+                .valores_conciliados()
+                .iter()
+                .map(|valor| localizar(valor.campo_efd))
+                .collect::<SpedResult<Vec<_>>>()?,
+            localizar("num_item")?,
+        )),
+        None => None,
+    };
+
+    // Valores deste arquivo: mesclados em `info.valores` ao final da leitura
+    let mut valores_do_arquivo: HashMap<ItemConciliado, ValoresDoItem> = HashMap::new();
+
+    // 7. Filtros das linhas (tipo de crédito, CST, natureza da BC, CFOP, período e estabelecimento)
+    let filtros = config.filtros.localizar(localizar)?;
 
     // 8. Processamento dos Registros
-    let mut keys_efd = HashSet::new();
+    // Os componentes são incluídos uma vez por arquivo: cada arquivo registra suas correlacionadas
+    let mut incluidos = ComponentesIncluidos::default();
 
     // 9. Iteração funcional sobre os registros
     for (idx, result) in rdr.records().enumerate() {
         // Se houver um erro de leitura (incluindo número de colunas errado)
-        let record =
-            result.map_err(|e| SpedError::from_csv(e, config.efd_path.clone(), idx + 2))?;
+        let record = result.map_err(|e| SpedError::from_csv(e, efd_path.to_path_buf(), idx + 2))?;
+        info.linhas_lidas += 1;
+
+        if let Some(filtro) = filtros.excluir(&record) {
+            *info.linhas_excluidas.entry(filtro).or_default() += 1;
+            continue;
+        }
 
         if let Some(content) = record.get(idx_chave) {
-            // Limpeza de não-dígitos
-            let clean_key = RE_NON_DIGITS.replace_all(content, "");
+            // Limpeza de separadores (o CNPJ da chave pode conter letras)
+            if let Some(chave) = limpar_chave(content) {
+                // Chaves com DV inválido vão para relatório próprio
+                if !chave.dv_valido() {
+                    info.invalidas.push(ChaveInvalida {
+                        chave,
+                        arquivo: efd_path.to_path_buf(),
+                        linha: idx + 2,
+                    });
+                    continue;
+                }
 
-            if RE_CHAVE_44.is_match(&clean_key) {
-                // Transformamos em String apenas uma vez
-                let chave = clean_key.into_owned();
+                let origem = OrigemEfd {
+                    arquivo: efd_path.to_path_buf(),
+                    periodo_apuracao: record.get(idx_periodo).unwrap_or_default().to_string(),
+                    motivo: Motivo::Declarada,
+                };
+
+                // Consistência entre o AAMM da chave e as datas da EFD
+                if let Some([idx_efd_line, idx_emissao, idx_lancamento]) = idx_periodos {
+                    let campo = |i: usize| record.get(i).unwrap_or_default();
+
+                    for (motivo, defasagem) in verificar_periodo(
+                        &chave,
+                        campo(idx_emissao),
+                        campo(idx_lancamento),
+                        campo(idx_periodo),
+                        config.meses_defasagem,
+                    ) {
+                        info.inconsistencias_de_periodo
+                            .push(InconsistenciaDePeriodo {
+                                chave,
+                                arquivo: efd_path.to_path_buf(),
+                                linha: idx + 2,
+                                efd_line: campo(idx_efd_line).to_string(),
+                                data_emissao: campo(idx_emissao).to_string(),
+                                data_lancamento: campo(idx_lancamento).to_string(),
+                                periodo_apuracao: campo(idx_periodo).to_string(),
+                                defasagem,
+                                motivo,
+                            });
+                    }
+                }
 
-                // Primeiro adicionamos chaves correlacionadas (usa a referência &chave)
-                add_correlated_keys_to_info(config, &chave, &mut keys_efd);
+                // Primeiro adicionamos chaves correlacionadas (cada componente uma única vez)
+                let mut correlacionadas = HashMap::new();
+                config.relacoes.adicionar_correlacionadas(
+                    &chave,
+                    &config.regras,
+                    &mut incluidos,
+                    &mut correlacionadas,
+                );
 
-                // Depois movemos a chave principal para o set (evita .clone())
-                keys_efd.insert(chave);
+                for (correlacionada, motivo) in correlacionadas {
+                    info.motivos.entry(correlacionada).or_insert(motivo);
+                    info.origens
+                        .entry(correlacionada)
+                        .or_default()
+                        .insert(OrigemEfd {
+                            motivo,
+                            ..origem.clone()
+                        });
+                }
+
+                // Depois adicionamos a chave principal ao set (regra a)
+                if config.regras.chave_direta {
+                    info.chaves.insert(chave);
+                }
+                info.declaradas.insert(chave);
+                info.origens.entry(chave).or_default().insert(origem);
+
+                // Conciliação de valores: soma das linhas da chave (e do item)
+                if let Some((idx_valores, idx_item)) = &idx_conciliacao {
+                    let campo = |i: usize| record.get(i).unwrap_or_default();
+                    let valores = ValoresDoItem::from_campos(idx_valores.iter().map(|&i| campo(i)));
+
+                    valores_do_arquivo
+                        .entry((chave, numero_do_item(campo(*idx_item))))
+                        .or_default()
+                        .somar(&valores);
+                }
+            } else if let Some([idx_cnpj, idx_modelo, idx_numero]) = idx_sem_chave {
+                // Documento sem chave: identificação por CNPJ + Número
+                let campo = |i: usize| record.get(i).unwrap_or_default();
+
+                if let Some(documento) =
+                    DocumentoSemChave::new(campo(idx_cnpj), campo(idx_numero), campo(idx_modelo))
+                {
+                    info.modelos_sem_chave.insert(documento.modelo.clone());
+                    info.documentos_sem_chave.insert(documento);
+                }
             }
         }
     }
 
-    Ok(keys_efd)
+    mesclar_valores_do_arquivo(efd_path, valores_do_arquivo, &mut info.valores);
+
+    Ok(())
 }
 
-fn add_correlated_keys_to_info(config: &Config, chave: &str, info: &mut HashSet<String>) {
-    let fontes = [&config.nfe_ctes, &config.cte_nfes, &config.cte_complementar];
+/// Mescla os valores (conciliação) de um arquivo da EFD aos valores dos arquivos anteriores.
+///
+/// Os valores de cada chave provêm de um único arquivo: se a chave consta de mais de um
+/// arquivo (ex: escrituração original e retificadora), prevalece o último arquivo
+/// informado em `--efd-path`, e um aviso é exibido.
+fn mesclar_valores_do_arquivo(
+    efd_path: &Path,
+    valores_do_arquivo: HashMap<ItemConciliado, ValoresDoItem>,
+    valores: &mut HashMap<ItemConciliado, ValoresDoItem>,
+) {
+    let chaves_do_arquivo: HashSet<ChaveAcesso> =
+        valores_do_arquivo.keys().map(|&(chave, _)| chave).collect();
+
+    let mut substituidas = HashSet::new();
+    valores.retain(|(chave, _), _| {
+        let substituir = chaves_do_arquivo.contains(chave);
+        if substituir {
+            substituidas.insert(*chave);
+        }
+        !substituir
+    });
+
+    if !substituidas.is_empty() {
+        eprintln!(
+            " [AVISO] {} chaves com valores em mais de um arquivo da EFD: prevalecem os valores de <{}>.",
+            fmt_milhares(substituidas.len()),
+            efd_path.display()
+        );
+    }
 
-    for mapa in fontes {
-        if let Some(itens) = mapa.get(chave) {
-            info.extend(itens.iter().cloned());
+    valores.extend(valores_do_arquivo);
+}
+
+impl InfoEfd {
+    /// Motivo pelo qual a chave faz parte do filtro (`None` se não faz parte).
+    ///
+    /// Chaves declaradas na EFD prevalecem sobre as correlacionadas.
+    pub fn motivo(&self, chave: &ChaveAcesso) -> Option<Motivo> {
+        if self.declaradas.contains(chave) {
+            return Some(Motivo::Declarada);
         }
+        self.motivos.get(chave).copied()
     }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum TipoDeArquivo {
+    /// EFD Contribuições no formato CSV (colunas de `COLUNAS_EFD`).
     EFDContrib,
+    /// Arquivo original da EFD Contribuições (SPED `.txt`), convertido para CSV antes da leitura.
+    EFDContribTxt,
+    /// EFD ICMS/IPI no formato CSV (colunas de `COLUNAS_EFD_ICMS_IPI`).
+    EFDIcmsIpi,
     DocFiscais,
 }
 
+/// Escrituração digital da qual são obtidas as chaves (opção `--escrituracao`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum EscrituracaoEfd {
+    /// EFD Contribuições (PIS/PASEP e COFINS)
+    #[default]
+    Contribuicoes,
+    /// EFD ICMS/IPI (chaves dos registros C100 e D100)
+    IcmsIpi,
+}
+
+impl EscrituracaoEfd {
+    /// Nome da escrituração utilizado nos relatórios.
+    pub fn nome(&self) -> &'static str {
+        match self {
+            EscrituracaoEfd::Contribuicoes => "EFD Contribuições",
+            EscrituracaoEfd::IcmsIpi => "EFD ICMS/IPI",
+        }
+    }
+
+    /// Mapeamento das colunas do arquivo CSV da escrituração.
+    pub fn colunas(&self) -> &'static HashMap<&'static str, &'static str> {
+        match self {
+            EscrituracaoEfd::Contribuicoes => &COLUNAS_EFD,
+            EscrituracaoEfd::IcmsIpi => &COLUNAS_EFD_ICMS_IPI,
+        }
+    }
+
+    /// Colunas de valores do relatório de créditos sobre documentos cancelados.
+    ///
+    /// ```
+    /// use reter_linhas_com_info_das_chaves::EscrituracaoEfd;
+    ///
+    /// assert_eq!(
+    ///     EscrituracaoEfd::IcmsIpi.colunas_de_credito(),
+    ///     ["valor_bc_icms", "valor_de_icms", "valor_de_ipi"]
+    /// );
+    /// ```
+    pub fn colunas_de_credito(&self) -> [&'static str; 3] {
+        match self {
+            EscrituracaoEfd::Contribuicoes => {
+                ["valor_bc_contrib", "valor_de_pis", "valor_de_cofins"]
+            }
+            EscrituracaoEfd::IcmsIpi => ["valor_bc_icms", "valor_de_icms", "valor_de_ipi"],
+        }
+    }
+
+    /// Valores comparados na conciliação entre a EFD e os Documentos Fiscais (`--conciliar`).
+    pub fn valores_conciliados(&self) -> &'static [ValorConciliado] {
+        match self {
+            EscrituracaoEfd::Contribuicoes => &VALORES_CONCILIADOS_CONTRIBUICOES,
+            EscrituracaoEfd::IcmsIpi => &VALORES_CONCILIADOS_ICMS_IPI,
+        }
+    }
+}
+
+/// Arquivo da EFD informado em `--efd-path`, seu formato e sua codificação.
+#[derive(Debug, Clone)]
+pub struct ArquivoEfd {
+    pub path: PathBuf,
+    pub tipo: TipoDeArquivo,
+    /// Codificação de leitura: o CSV convertido do SPED `.txt` é sempre UTF-8.
+    pub codificacao: Codificacao,
+}
+
+/// Campos cujas colunas são exigidas apenas com a opção que as utiliza
+/// (`--sem-chave`, `--verificar-periodos`, `--relacoes-dos-documentos` e `--conciliar`),
+/// além da situação de cancelamento, opcional nos Documentos Fiscais.
+const CAMPOS_DAS_OPCOES: [&str; 9] = [
+    "cnpj_participante",
+    "modelo_doc_fiscal",
+    "num_doc_fiscal",
+    "data_emissao_nota",
+    "data_lancamento",
+    "chave_de_acesso",
+    "num_item",
+    "valor_total",
+    "nota_cancelada",
+];
+
 pub fn verificar_existencia_de_colunas_essenciais(
     column_names: &[&str],
     tipo: TipoDeArquivo,
@@ -449,11 +847,18 @@ pub fn verificar_existencia_de_colunas_essenciais(
     }
 
     // 3. Determinar quais colunas são essenciais para este tipo de arquivo
-    let colunas_essenciais = match tipo {
-        TipoDeArquivo::EFDContrib => config.colunas_efd.values(),
-        TipoDeArquivo::DocFiscais => config.colunas_doc.values(),
+    // As colunas das opções (`CAMPOS_DAS_OPCOES`) são localizadas apenas quando a opção está ativa.
+    let colunas = match tipo {
+        TipoDeArquivo::EFDContrib | TipoDeArquivo::EFDContribTxt => config.colunas_efd,
+        TipoDeArquivo::EFDIcmsIpi => config.colunas_efd_icms_ipi,
+        TipoDeArquivo::DocFiscais => config.colunas_doc,
     };
 
+    let colunas_essenciais = colunas
+        .iter()
+        .filter(|(campo, _)| !CAMPOS_DAS_OPCOES.contains(campo))
+        .map(|(_, coluna)| coluna);
+
     // 4. Validar se todas as colunas essenciais estão presentes (Estilo Funcional)
     // find() retorna a primeira coluna que NÃO está contida no cabeçalho
     if let Some(ausente) = colunas_essenciais
@@ -480,49 +885,325 @@ pub fn verificar_existencia_de_colunas_essenciais(
     Ok(())
 }
 
-/// Processamento Paralelo de CSVs de Documentos Fiscais
-pub fn read_csv_files(config: &Config, keys_efd: &HashSet<String>) -> SpedResult<HashSet<String>> {
-    // Usamos AtomicUsize para permitir que múltiplas threads somem o contador sem travar (lock-free)
-    let total_itens = AtomicUsize::new(0);
+/// Resultado da análise dos arquivos de Documentos Fiscais.
+#[derive(Debug, Default)]
+pub struct InfoDocs {
+    /// Chaves procuradas (filtro da EFD) e encontradas nos Documentos Fiscais.
+    pub encontradas: HashSet<ChaveAcesso>,
+    /// Todas as chaves dos Documentos Fiscais (preenchido apenas com `--corrigir-chaves`).
+    pub todas: HashSet<ChaveAcesso>,
+    /// Documentos sem chave da EFD encontrados por CNPJ + Número.
+    pub encontrados_sem_chave: HashSet<DocumentoSemChave>,
+    /// Chaves da EFD encontradas e canceladas (coluna `nota_cancelada`).
+    pub canceladas: HashSet<ChaveAcesso>,
+    /// Ocorrências de chaves com DV inválido.
+    pub invalidas: Vec<ChaveInvalida>,
+    /// Número de itens (linhas) analisados.
+    pub total_de_itens: usize,
+    /// Codificação original dos Documentos Fiscais, se diferente de UTF-8.
+    pub codificacao_original: Option<Codificacao>,
+    /// Valores dos itens das chaves declaradas na EFD (apenas com `--conciliar`).
+    /// Cada item numerado é considerado uma única vez (ver `acumular_item`).
+    pub valores: HashMap<ItemConciliado, ValoresDoItem>,
+    /// Valor total da nota de cada chave declarada na EFD (apenas com `--conciliar`).
+    pub totais_das_notas: HashMap<ChaveAcesso, Valor>,
+    /// Conteúdos não reconhecidos da coluna `nota_cancelada` e o número de linhas de cada um.
+    pub situacoes_nao_reconhecidas: BTreeMap<String, usize>,
+}
 
+impl InfoDocs {
+    /// Mescla os resultados parciais de dois arquivos (ou de duas threads).
+    pub(crate) fn merge(mut self, other: InfoDocs) -> InfoDocs {
+        self.encontradas.extend(other.encontradas);
+        self.todas.extend(other.todas);
+        self.encontrados_sem_chave
+            .extend(other.encontrados_sem_chave);
+        self.canceladas.extend(other.canceladas);
+        self.invalidas.extend(other.invalidas);
+        self.total_de_itens += other.total_de_itens;
+        self.codificacao_original = self.codificacao_original.or(other.codificacao_original);
+        for (item, valores) in other.valores {
+            acumular_item(&mut self.valores, item, valores);
+        }
+        for (chave, total) in other.totais_das_notas {
+            self.totais_das_notas.entry(chave).or_insert(total);
+        }
+        for (situacao, num) in other.situacoes_nao_reconhecidas {
+            *self.situacoes_nao_reconhecidas.entry(situacao).or_default() += num;
+        }
+        self
+    }
+}
+
+/// Campos de uma linha (item) de Documento Fiscal utilizados na correspondência com a EFD.
+pub struct LinhaDoc<'a> {
+    pub chave: Option<ChaveAcesso>,
+    /// Chave da NFe informada na Coluna 2 (`chave_de_acesso`), apenas com a regra b)
+    /// e a opção `--relacoes-dos-documentos`.
+    pub chave_nfe: Option<ChaveAcesso>,
+    pub cnpj: &'a str,
+    pub numero: &'a str,
+    /// Conteúdo da coluna `nota_cancelada` (`None` se o arquivo não contém a coluna).
+    pub cancelada: Option<&'a str>,
+    /// Arquivo e número da linha (ou do item), para o relatório de DV inválido.
+    pub ocorrencia: (&'a Path, usize),
+    /// Campos da conciliação de valores: número do item, valor total da nota e valores
+    /// na ordem de `EscrituracaoEfd::valores_conciliados` (vazio sem `--conciliar`).
+    pub num_item: &'a str,
+    pub valor_total: &'a str,
+    pub valores: Vec<&'a str>,
+}
+
+/// Verifica se a linha de Documento Fiscal corresponde à EFD e atualiza as estatísticas.
+///
+/// Retorna o motivo da retenção (coluna `Motivo da Retenção`) ou `None`
+/// se a linha deve ser descartada.
+///
+/// Uma NFe declarada na EFD e informada apenas na Coluna 2 de um CTe (regra b)
+/// também é considerada encontrada:
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::{
+///     ChaveAcesso, Config, InfoDocs, InfoEfd, LinhaDoc, Motivo, classificar_linha,
+///     imprimir_chaves_nao_encontradas,
+/// };
+/// use std::path::Path;
+///
+/// let cte: ChaveAcesso = "35240112345678000190570010000000011000000017".parse().unwrap();
+/// let nfe: ChaveAcesso = "35240112345678000190550010000012341123456786".parse().unwrap();
+///
+/// // NFe declarada na EFD; nos Documentos Fiscais, apenas na Coluna 2 da linha do CTe
+/// let mut filter = InfoEfd::default();
+/// filter.chaves.insert(nfe);
+/// filter.declaradas.insert(nfe);
+///
+/// let linha = LinhaDoc {
+///     chave: Some(cte),
+///     chave_nfe: Some(nfe),
+///     cnpj: "",
+///     numero: "",
+///     cancelada: None,
+///     ocorrencia: (Path::new("docs.csv"), 2),
+///     num_item: "",
+///     valor_total: "",
+///     valores: Vec::new(),
+/// };
+///
+/// let mut info = InfoDocs::default();
+/// let motivo = classificar_linha(&linha, &Config::default(), &filter, &mut info);
+///
+/// assert_eq!(motivo, Some(Motivo::CteDaNfe(nfe)));
+/// assert!(imprimir_chaves_nao_encontradas(&filter.chaves, &info.encontradas, "EFD").is_empty());
+/// ```
+pub fn classificar_linha(
+    linha: &LinhaDoc,
+    config: &Config,
+    filter: &InfoEfd,
+    info: &mut InfoDocs,
+) -> Option<Motivo> {
+    if let Some(chave) = linha.chave {
+        // Todas as chaves são retidas para a busca de correções prováveis
+        if config.corrigir_chaves {
+            info.todas.insert(chave);
+        }
+
+        // Chaves com DV inválido vão para relatório próprio
+        if !chave.dv_valido() {
+            let (arquivo, numero_da_linha) = linha.ocorrencia;
+            info.invalidas.push(ChaveInvalida {
+                chave,
+                arquivo: arquivo.to_path_buf(),
+                linha: numero_da_linha,
+            });
+            return None;
+        }
+    }
+
+    // OTIMIZAÇÃO 2: Verificação de existência no HashSet
+    // ChaveAcesso tem tamanho fixo, então o .contains() é extremamente eficiente
+    match (linha.chave, linha.chave_nfe) {
+        (Some(chave), _) if filter.chaves.contains(&chave) => {
+            registrar_encontrada(chave, linha, config, filter, info);
+            filter.motivo(&chave)
+        }
+        // Regra b): NFe declarada na EFD informada na Coluna 2 (CTe com uma única NFe)
+        (chave, Some(nfe))
+            if chave != Some(nfe) && nfe.dv_valido() && filter.declaradas.contains(&nfe) =>
+        {
+            // A NFe foi encontrada, ainda que apenas na Coluna 2
+            registrar_encontrada(nfe, linha, config, filter, info);
+            Some(Motivo::CteDaNfe(nfe))
+        }
+        // Modo secundário: documentos da EFD sem chave, por CNPJ + Número
+        _ if config.sem_chave => {
+            // Com chave válida, o modelo da chave deve coincidir com o modelo da EFD;
+            // sem chave, o modelo é desconhecido e vale qualquer modelo da EFD.
+            let modelo_da_chave = linha.chave.map(|chave| chave.modelo().to_string());
+            let modelos: Vec<&str> = match &modelo_da_chave {
+                Some(modelo) => vec![modelo],
+                None => filter
+                    .modelos_sem_chave
+                    .iter()
+                    .map(String::as_str)
+                    .collect(),
+            };
+
+            let encontrados: Vec<DocumentoSemChave> = modelos
+                .into_iter()
+                .filter_map(|modelo| DocumentoSemChave::new(linha.cnpj, linha.numero, modelo))
+                .filter(|doc| filter.documentos_sem_chave.contains(doc))
+                .collect();
+
+            if encontrados.is_empty() {
+                return None;
+            }
+
+            info.encontrados_sem_chave.extend(encontrados);
+            Some(Motivo::SemChave)
+        }
+        _ => None,
+    }
+}
+
+/// Registra a chave da EFD encontrada nos Documentos Fiscais, com a situação de
+/// cancelamento e os valores da linha (conciliação).
+fn registrar_encontrada(
+    chave: ChaveAcesso,
+    linha: &LinhaDoc,
+    config: &Config,
+    filter: &InfoEfd,
+    info: &mut InfoDocs,
+) {
+    // Inserimos no set de encontrados
+    info.encontradas.insert(chave);
+
+    // Situação do documento: crédito da EFD sobre documento cancelado
+    if let Some(cancelada) = linha.cancelada {
+        match eh_cancelada(cancelada) {
+            Some(true) => {
+                info.canceladas.insert(chave);
+            }
+            Some(false) => {}
+            None => {
+                *info
+                    .situacoes_nao_reconhecidas
+                    .entry(cancelada.trim().to_string())
+                    .or_default() += 1;
+            }
+        }
+    }
+
+    // Conciliação de valores: apenas chaves declaradas na EFD
+    if config.conciliacao.is_some() && filter.declaradas.contains(&chave) {
+        acumular_item(
+            &mut info.valores,
+            (chave, numero_do_item(linha.num_item)),
+            ValoresDoItem::from_campos(linha.valores.iter().copied()),
+        );
+
+        if let Some(total) = Valor::parse(linha.valor_total) {
+            info.totais_das_notas.entry(chave).or_insert(total);
+        }
+    }
+}
+
+/// Coluna adicionada ao arquivo final indicando por que a linha foi retida
+/// (chave declarada na EFD, chave correlacionada ou documento sem chave).
+pub const COLUNA_MOTIVO: &str = "Motivo da Retenção";
+
+/// Localiza a posição, no cabeçalho, da coluna associada ao campo informado.
+pub fn localizar_coluna(
+    column_names: &[&str],
+    campo: &str,
+    tipo: TipoDeArquivo,
+    config: &Config,
+    arquivo: &Path,
+) -> SpedResult<usize> {
+    let colunas = match tipo {
+        TipoDeArquivo::EFDContrib | TipoDeArquivo::EFDContribTxt => config.colunas_efd,
+        TipoDeArquivo::EFDIcmsIpi => config.colunas_efd_icms_ipi,
+        TipoDeArquivo::DocFiscais => config.colunas_doc,
+    };
+
+    let col_name = colunas
+        .get(campo)
+        .ok_or_else(|| SpedError::Config(format!("Configuração '{campo}' ausente")))?;
+
+    column_names
+        .iter()
+        .position(|col| col == col_name)
+        .ok_or_else(|| SpedError::MissingEssentialColumn {
+            arquivo: arquivo.to_path_buf(),
+            coluna: col_name.to_string(),
+            tipo,
+        })
+}
+
+/// Processamento Paralelo de CSVs de Documentos Fiscais
+pub fn read_csv_files(config: &Config, info_efd: &InfoEfd) -> SpedResult<InfoDocs> {
     // O Rayon irá processar os arquivos em paralelo.
-    // O flat_map transforma o Stream de (Set, Count) em um Stream único de Chaves.
-    let keys_encontradas: HashSet<String> = config
+    // O reduce mescla os resultados parciais de cada arquivo em um único InfoDocs.
+    let info = config
         .arquivos_csv
         .par_iter()
-        .flat_map(|path| {
-            let (set, count) = process_single_csv(path.to_path_buf(), config, keys_efd)
+        .map(|path| {
+            // Diretórios contêm arquivos XML de NF-e (ver `--xml-nfe`)
+            let resultado = if path.is_dir() {
+                process_xml_nfe_dir(path, config, info_efd)
+            } else {
+                process_single_csv(path.to_path_buf(), config, info_efd)
+            };
+
+            resultado
                 .map_err(|e| {
                     eprintln!(" [ERRO] Arquivo <{:?}>: {}", path, e);
                     e
                 })
-                .unwrap_or_default(); // Se falhar, retorna set vazio e contagem 0
-
-            // Incrementa o contador global de forma segura entre threads
-            total_itens.fetch_add(count, Ordering::Relaxed);
-
-            // Transforma o HashSet local em um iterador paralelo para o flat_map
-            set.into_par_iter()
+                .unwrap_or_default() // Se falhar, retorna resultado vazio
         })
-        .collect(); // Coleta todas as chaves achatadas no HashSet final
+        .reduce(InfoDocs::default, InfoDocs::merge);
 
     println!(
         " Total de itens analisados nos documentos fiscais: {}",
-        fmt_milhares(total_itens.load(Ordering::Relaxed))
+        fmt_milhares(info.total_de_itens)
     );
 
-    Ok(keys_encontradas)
+    Ok(info)
 }
 
-fn process_single_csv(
-    path: PathBuf,
-    config: &Config,
-    filter: &HashSet<String>,
-) -> SpedResult<(HashSet<String>, usize)> {
+/// Chave de 44 posições contida no campo, ignorando separadores.
+///
+/// A chave começa e termina com dígitos: prefixos e sufixos de texto (ex: `NFe`)
+/// são descartados. No interior, apenas os separadores são removidos, pois as
+/// letras do CNPJ alfanumérico fazem parte da chave (validada por `RE_CHAVE_44`).
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::limpar_chave;
+///
+/// let chave = "35240112345678000190550010000012341123456786";
+///
+/// assert_eq!(limpar_chave(&format!("NFe{chave}")).unwrap().to_string(), chave);
+/// assert!(limpar_chave("3524 0112 3456 7800 0190 5500 1000 0012 3411 2345 6786").is_some());
+/// assert!(limpar_chave("35260712abc34501de35550010000012341123456799").is_some());
+/// assert!(limpar_chave(&format!("1{chave}")).is_none());
+/// ```
+pub fn limpar_chave(content: &str) -> Option<ChaveAcesso> {
+    // OTIMIZAÇÃO 1: Limpeza manual (muito mais rápida que Regex em loop)
+    let clean_key: String = content
+        .trim_matches(|c: char| !c.is_ascii_digit())
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    clean_key.parse::<ChaveAcesso>().ok()
+}
+
+fn process_single_csv(path: PathBuf, config: &Config, filter: &InfoEfd) -> SpedResult<InfoDocs> {
     let delimiter = b';';
 
     // 1. Abertura eficiente do arquivo com BufReader aumentado para 128KB
-    let file = File::open(path.clone())?;
+    let file = abrir_decodificado(&path, config.codificacao)?;
     let mut rdr = csv::ReaderBuilder::new()
         .delimiter(delimiter)
         .has_headers(true)
@@ -542,20 +1223,61 @@ fn process_single_csv(
         path.clone(),
     )?;
 
-    // 2. Localização da coluna alvo (Chave de 44 dígitos)
-    let target_col_name = config
-        .colunas_doc
-        .get("chave44_digitos")
-        .ok_or_else(|| SpedError::Config("Configuração 'chave44_digitos' ausente".into()))?;
+    // 2. Localização das colunas alvo (Chave de 44 dígitos e identificação sem chave)
+    let localizar = |campo| {
+        localizar_coluna(
+            &column_names,
+            campo,
+            TipoDeArquivo::DocFiscais,
+            config,
+            &path,
+        )
+    };
 
-    let target_col_idx = column_names
-        .iter()
-        .position(|col| col == target_col_name)
-        .ok_or_else(|| SpedError::MissingEssentialColumn {
-            arquivo: path.clone(),
-            coluna: target_col_name.to_string(),
-            tipo: TipoDeArquivo::DocFiscais,
-        })?;
+    let target_col_idx = localizar("chave44_digitos")?;
+
+    // Coluna 2 (NFe transportada pelo CTe): apenas com `--relacoes-dos-documentos`
+    let idx_chave_nfe = if config.relacoes_dos_documentos {
+        Some(localizar("chave_de_acesso")?)
+    } else {
+        None
+    };
+
+    // Coluna opcional: sem ela, a situação de cancelamento não é verificada neste arquivo
+    let idx_cancelada = localizar("nota_cancelada").ok();
+    if idx_cancelada.is_none() {
+        eprintln!(
+            " [AVISO] Arquivo <{}> sem a coluna {:?}: documentos cancelados não verificados.",
+            path.display(),
+            config.colunas_doc["nota_cancelada"],
+        );
+    }
+
+    // Colunas da identificação sem chave: CNPJ e número (apenas com `--sem-chave`)
+    let idx_sem_chave = if config.sem_chave {
+        Some([
+            localizar("cnpj_participante")?,
+            localizar("num_doc_fiscal")?,
+        ])
+    } else {
+        None
+    };
+
+    // Colunas de valores, do número do item e do valor total (apenas com `--conciliar`)
+    // Nos valores, `None` indica valor exclusivo da EFD.
+    let idx_conciliacao = match config.conciliacao {
+        Some(_) => Some((
+            config
+                .escrituracao
+                .valores_conciliados()
+                .iter()
+                .map(|valor| valor.campo_doc.map(&localizar).transpose())
+                .collect::<SpedResult<Vec<_>>>()?,
+            localizar("num_item")?,
+            localizar("valor_total")?,
+        )),
+        None => None,
+    };
 
     // 3. Preparação do Writer temporário com buffer de 1MB para escrita
     let temp_file = {
@@ -569,61 +1291,90 @@ fn process_single_csv(
 
     // Grava cabeçalho apenas se for o primeiro arquivo
     if config.arquivos_csv.first() == Some(&path) {
-        wtr.write_record(rdr.headers()?)?;
+        let mut headers = rdr.headers()?.clone();
+        headers.push_field(COLUNA_MOTIVO);
+        wtr.write_record(&headers)?;
     }
 
     // Fora do loop, alocamos os buffers uma única vez
     let mut record = csv::StringRecord::new(); // Buffer de entrada
     let mut out_record = csv::ByteRecord::new(); // Buffer de saída (reutiliza memória interna)
 
-    let mut found_in_file = HashSet::new();
-    let mut count = 0;
+    let mut info = InfoDocs::default();
 
     // rdr.read_record preenche o buffer 'record' limpando o conteúdo anterior (sem desalocar)
     while rdr.read_record(&mut record)? {
-        count += 1;
-
-        if let Some(content) = record.get(target_col_idx) {
-            // OTIMIZAÇÃO 1: Limpeza de dígitos manual (muito mais rápida que Regex em loop)
-            let clean_key: String = content.chars().filter(|c| c.is_ascii_digit()).collect();
-
-            // OTIMIZAÇÃO 2: Verificação de tamanho e existência no HashSet
-            // clean_key é String, então o .contains() é extremamente eficiente
-            if clean_key.len() == 44 && filter.contains(&clean_key) {
-                // Inserimos no set de encontrados
-                found_in_file.insert(clean_key);
-
-                // OTIMIZAÇÃO 3: Construção da linha de saída sem alocar Vec<String>
-                out_record.clear(); // Reseta os índices, mas mantém o buffer de bytes alocado
-
-                for field in record.iter() {
-                    // OTIMIZAÇÃO 4: Só chama o Regex se realmente houver espaços duplos
-                    if field.contains("  ") {
-                        let normalized = RE_MULTISPACE.replace_all(field, " ");
-                        out_record.push_field(normalized.as_bytes());
-                    } else {
-                        // Fast-path: copia os bytes originais diretamente para o buffer de saída
-                        out_record.push_field(field.as_bytes());
-                    }
-                }
+        info.total_de_itens += 1;
+
+        let campo = |i: usize| record.get(i).unwrap_or_default();
+
+        let linha = LinhaDoc {
+            chave: limpar_chave(campo(target_col_idx)),
+            chave_nfe: idx_chave_nfe
+                .filter(|_| config.regras.cte_nfe_unica)
+                .and_then(|i| limpar_chave(campo(i))),
+            cnpj: idx_sem_chave.map(|[i, _]| campo(i)).unwrap_or_default(),
+            numero: idx_sem_chave.map(|[_, i]| campo(i)).unwrap_or_default(),
+            cancelada: idx_cancelada.map(campo),
+            // A linha 1 é o cabeçalho
+            ocorrencia: (path.as_path(), info.total_de_itens + 1),
+            num_item: idx_conciliacao
+                .as_ref()
+                .map(|(_, i, _)| campo(*i))
+                .unwrap_or_default(),
+            valor_total: idx_conciliacao
+                .as_ref()
+                .map(|(_, _, i)| campo(*i))
+                .unwrap_or_default(),
+            valores: idx_conciliacao
+                .iter()
+                .flat_map(|(idx_valores, _, _)| idx_valores)
+                .map(|idx| idx.map(campo).unwrap_or_default())
+                .collect(),
+        };
+
+        let Some(motivo) = classificar_linha(&linha, config, filter, &mut info) else {
+            continue;
+        };
 
-                // Escreve o registro completo (o Writer gerencia delimitadores e quebras de linha)
-                wtr.write_byte_record(&out_record)?;
+        // OTIMIZAÇÃO 3: Construção da linha de saída sem alocar Vec<String>
+        out_record.clear(); // Reseta os índices, mas mantém o buffer de bytes alocado
+
+        for field in record.iter() {
+            // OTIMIZAÇÃO 4: Só chama o Regex se realmente houver espaços duplos
+            if field.contains("  ") {
+                let normalized = RE_MULTISPACE.replace_all(field, " ");
+                out_record.push_field(normalized.as_bytes());
+            } else {
+                // Fast-path: copia os bytes originais diretamente para o buffer de saída
+                out_record.push_field(field.as_bytes());
             }
         }
+
+        out_record.push_field(motivo.to_string().as_bytes());
+
+        // Escreve o registro completo (o Writer gerencia delimitadores e quebras de linha)
+        wtr.write_byte_record(&out_record)?;
     }
 
+    // Codificação detectada (ou informada) do arquivo lido
+    info.codificacao_original = match rdr.get_ref().get_ref().codificacao() {
+        Codificacao::Auto | Codificacao::Utf8 => None,
+        codificacao => Some(codificacao),
+    };
+
     wtr.flush()?;
-    Ok((found_in_file, count))
+    Ok(info)
 }
 
-pub fn merge_files(config: &Config) -> SpedResult<()> {
+/// Mescla os arquivos temporários no arquivo final (target), gravado na codificação `saida`.
+pub fn merge_files(config: &Config, saida: Codificacao) -> SpedResult<()> {
     println!(
         "\n Mesclar arquivos temporários em <{}>...\n",
         config.target.display()
     );
 
-    let mut final_file = File::create(&config.target)?;
+    let mut final_file = BufWriter::new(File::create(&config.target)?);
     let mut seen_lines = HashSet::new();
     let max = config
         .arquivos_csv
@@ -657,7 +1408,8 @@ pub fn merge_files(config: &Config) -> SpedResult<()> {
                     let line_hash = blake3::hash(normalized_line.as_bytes()).to_string();
 
                     if seen_lines.insert(line_hash) {
-                        writeln!(final_file, "{}", normalized_line)?;
+                        final_file.write_all(&saida.codificar(&normalized_line))?;
+                        final_file.write_all(b"\n")?;
                     }
 
                     Ok(())
@@ -682,12 +1434,19 @@ pub fn merge_files(config: &Config) -> SpedResult<()> {
 }
 
 pub fn exibir_orientacoes_auditoria(config: &Config) {
+    match &config.arquivos_efd[..] {
+        [arquivo] => println!("\nPesquisar informações do arquivo: {:?}\n", arquivo.path),
+        arquivos => {
+            println!("\nPesquisar informações dos arquivos:\n");
+            for (i, arquivo) in arquivos.iter().enumerate() {
+                println!("{:6}: {}", i + 1, arquivo.path.display());
+            }
+            println!();
+        }
+    }
     println!(
-        "\nPesquisar informações do arquivo: {:?}\n",
-        config.efd_path
-    );
-    println!(
-        " 1.1 Foram analisadas as chaves NFe/CTe de 44 dígitos contidas na EFD Contribuições."
+        " 1.1 Foram analisadas as chaves NFe/CTe de 44 dígitos contidas na {}.",
+        config.escrituracao.nome()
     );
 
     // As colunas vêm do nosso LazyLock de colunas estáticas
@@ -698,44 +1457,94 @@ pub fn exibir_orientacoes_auditoria(config: &Config) {
     println!("  Coluna 1: '{}'", col1);
     println!("  Coluna 2: '{}'\n", col2);
 
-    println!(" 1.2 Foram pesquisadas informações complementares (Transitividade):");
-    println!("  - Chaves complementares de CTes (transporte subcontratado).");
-    println!("  - NFes vinculadas a CTes com múltiplos documentos (DIVERSOS).");
-    println!("  - Estas chaves são obtidas via análise de XML ou chaves complementares.\n");
+    let regras = &config.regras;
+
+    if regras.nfe_via_cte || regras.cte_complementar || regras.nfe_referenciada {
+        if regras.um_salto {
+            println!(
+                " 1.2 Foram pesquisadas informações complementares (apenas relações diretas):"
+            );
+        } else {
+            println!(" 1.2 Foram pesquisadas informações complementares (Transitividade):");
+        }
+        if regras.cte_complementar {
+            println!("  - Chaves complementares de CTes (transporte subcontratado).");
+        }
+        if regras.nfe_via_cte {
+            println!("  - NFes vinculadas a CTes com múltiplos documentos (DIVERSOS).");
+        }
+        if regras.nfe_referenciada {
+            println!("  - NFes referenciadas por outras NFes (devoluções e complementares).");
+        }
+        println!("  - Estas chaves são obtidas via análise de XML ou chaves complementares.\n");
+    } else {
+        println!(
+            " 1.2 Não foram pesquisadas informações complementares (regras c, d e e desativadas).\n"
+        );
+    }
+
+    let descricoes = [
+        (
+            regras.chave_direta,
+            "a) NFe está na Coluna 1 dos Docs Fiscais.",
+        ),
+        (
+            regras.cte_nfe_unica,
+            "b) NFe está na Coluna 2 (casos de CTe com uma única NFe).",
+        ),
+        (
+            regras.nfe_via_cte,
+            "c) NFe vinculada a CTe (casos de múltiplos itens obtidos via XML).",
+        ),
+        (
+            regras.cte_complementar,
+            "d) CTe original e CTe complementar (subcontratação).",
+        ),
+        (
+            regras.nfe_referenciada,
+            "e) NFe referenciada por outra NFe (devolução, complementar ou ajuste).",
+        ),
+    ];
 
     println!(" Serão adicionadas ao filtro as chaves onde:");
-    println!("  a) NFe está na Coluna 1 dos Docs Fiscais.");
-    println!("  b) NFe está na Coluna 2 (casos de CTe com uma única NFe).");
-    println!("  c) NFe vinculada a CTe (casos de múltiplos itens obtidos via XML).");
-    println!("  d) CTe original e CTe complementar (subcontratação).\n");
+    for (_, descricao) in descricoes.iter().filter(|(ativa, _)| *ativa) {
+        println!("  {descricao}");
+    }
+    println!();
+
+    if descricoes.iter().any(|(ativa, _)| !ativa) {
+        println!(" Regras desativadas (opção --desativar-regras):");
+        for (_, descricao) in descricoes.iter().filter(|(ativa, _)| !ativa) {
+            println!("  {descricao}");
+        }
+        println!();
+    }
 
     println!(" 2. Analisando chaves nos arquivos de Documentos Fiscais...\n");
 }
 
-pub fn imprimir_informacao_segregada(keys: &HashSet<String>, nome: &str, exibir_chaves: bool) {
+pub fn imprimir_informacao_segregada(keys: &HashSet<ChaveAcesso>, nome: &str, exibir_chaves: bool) {
     // 1. Agrupamento funcional: Código -> Quantidade
     // Usamos BTreeMap para que o loop de impressão seja ordenado pelo código do modelo
-    let hash_seg = keys.iter().filter(|key| key.len() >= 22).fold(
-        BTreeMap::<String, usize>::new(),
-        |mut acc, key| {
-            let codigo_doc_fiscal = &key[20..22];
-            *acc.entry(codigo_doc_fiscal.to_string()).or_insert(0) += 1;
+    let hash_seg = keys
+        .iter()
+        .fold(BTreeMap::<Modelo, usize>::new(), |mut acc, key| {
+            *acc.entry(key.modelo()).or_insert(0) += 1;
             acc
-        },
-    );
+        });
 
     let mut running_sum = 0;
 
     let max_len = hash_seg
         .keys()
-        .map(|codigo| get_modelo_documentos_fiscais(codigo).chars().count())
+        .map(|codigo| codigo.descricao().chars().count())
         .max()
         .unwrap_or_default();
 
     println!(" --- Relatório de Chaves: {} ---", nome);
 
     for (codigo, qtd) in &hash_seg {
-        let doc_nome = get_modelo_documentos_fiscais(codigo);
+        let doc_nome = codigo.descricao();
         running_sum += qtd;
 
         println!(
@@ -777,39 +1586,37 @@ pub fn fmt_milhares(n: usize) -> String {
 }
 
 pub fn imprimir_chaves_nao_encontradas(
-    keys_efd: &HashSet<String>,
-    keys_doc: &HashSet<String>,
-) -> HashSet<String> {
+    keys_efd: &HashSet<ChaveAcesso>,
+    keys_doc: &HashSet<ChaveAcesso>,
+    nome_efd: &str,
+) -> HashSet<ChaveAcesso> {
     let mut chaves_nao_encontradas = HashSet::new();
 
     // 1. Segregar todas as chaves por modelo (substr 20, 2)
-    let hash_seg = keys_efd.iter().filter(|chave| chave.len() >= 22).fold(
-        BTreeMap::<String, HashSet<String>>::new(),
+    let hash_seg = keys_efd.iter().fold(
+        BTreeMap::<Modelo, HashSet<ChaveAcesso>>::new(),
         |mut acc, chave| {
-            let codigo_doc_fiscal = &chave[20..22];
-            acc.entry(codigo_doc_fiscal.to_string())
-                .or_default()
-                .insert(chave.clone());
+            acc.entry(chave.modelo()).or_default().insert(*chave);
             acc
         },
     );
 
     let max_len = hash_seg
         .keys()
-        .map(|codigo| get_modelo_documentos_fiscais(codigo).chars().count())
+        .map(|codigo| codigo.descricao().chars().count())
         .max()
         .unwrap_or_default();
 
-    println!(" Chaves indicadas em EFD Contribuições e procuradas em Documentos Fiscais:");
+    println!(" Chaves indicadas em {nome_efd} e procuradas em Documentos Fiscais:");
 
     // 2. Iterar pelos modelos ordenados (BTreeMap já provê ordem)
     for (codigo, chaves) in &hash_seg {
-        let doc_nome = get_modelo_documentos_fiscais(codigo);
+        let doc_nome = codigo.descricao();
         let num = chaves.len(); // Total de chaves deste modelo
 
         // Diferença de conjuntos: o que tem na EFD mas não em Documentos Fiscais.
         // Chaves não encontradas segregadas por código do Documento Fiscal.
-        let faltantes: HashSet<String> = chaves
+        let faltantes: HashSet<ChaveAcesso> = chaves
             .iter()
             .filter(|&chave| !keys_doc.contains(chave))
             .cloned()
@@ -823,7 +1630,7 @@ pub fn imprimir_chaves_nao_encontradas(
         match sum {
             0 => {
                 println!(
-                    " Número de chaves em EFD Contribuições (modelo {} : {:<max_len$}) = {:>9} sendo que todas foram encontradas nos Documentos Fiscais.",
+                    " Número de chaves em {nome_efd} (modelo {} : {:<max_len$}) = {:>9} sendo que todas foram encontradas nos Documentos Fiscais.",
                     codigo,
                     doc_nome,
                     fmt_milhares(num),
@@ -831,7 +1638,7 @@ pub fn imprimir_chaves_nao_encontradas(
             }
             1 => {
                 println!(
-                    " Número de chaves em EFD Contribuições (modelo {} : {:<max_len$}) = {:>9} das quais apenas {:>7} não foi encontrada nos Documentos Fiscais.",
+                    " Número de chaves em {nome_efd} (modelo {} : {:<max_len$}) = {:>9} das quais apenas {:>7} não foi encontrada nos Documentos Fiscais.",
                     codigo,
                     doc_nome,
                     fmt_milhares(num),
@@ -840,7 +1647,7 @@ pub fn imprimir_chaves_nao_encontradas(
             }
             _ => {
                 println!(
-                    " Número de chaves em EFD Contribuições (modelo {} : {:<max_len$}) = {:>9} das quais {:>7} não foram encontradas nos Documentos Fiscais.",
+                    " Número de chaves em {nome_efd} (modelo {} : {:<max_len$}) = {:>9} das quais {:>7} não foram encontradas nos Documentos Fiscais.",
                     codigo,
                     doc_nome,
                     fmt_milhares(num),
@@ -858,11 +1665,48 @@ pub fn imprimir_chaves_nao_encontradas(
     chaves_nao_encontradas
 }
 
+/// Imprime o resumo dos documentos sem chave da EFD procurados por CNPJ + Número,
+/// segregados pelo modelo informado na EFD.
+pub fn imprimir_documentos_sem_chave(info_efd: &InfoEfd, info_docs: &InfoDocs, nome_efd: &str) {
+    // Modelo -> (Quantidade na EFD, Quantidade encontrada)
+    let hash_seg = info_efd.documentos_sem_chave.iter().fold(
+        BTreeMap::<&str, (usize, usize)>::new(),
+        |mut acc, documento| {
+            let entry = acc.entry(documento.modelo.as_str()).or_default();
+            entry.0 += 1;
+            if info_docs.encontrados_sem_chave.contains(documento) {
+                entry.1 += 1;
+            }
+            acc
+        },
+    );
+
+    let max_len = hash_seg
+        .keys()
+        .map(|codigo| get_modelo_documentos_fiscais(codigo).chars().count())
+        .max()
+        .unwrap_or_default();
+
+    println!(" Documentos sem chave em {nome_efd} procurados por CNPJ + Número:");
+
+    for (codigo, (num, encontrados)) in &hash_seg {
+        println!(
+            " Número de documentos sem chave (modelo {:>2} : {:<max_len$}) = {:>9} dos quais {:>7} foram encontrados nos Documentos Fiscais.",
+            codigo,
+            get_modelo_documentos_fiscais(codigo),
+            fmt_milhares(*num),
+            fmt_milhares(*encontrados)
+        );
+    }
+
+    println!();
+}
+
 /// Exporta chaves de acesso não encontradas para arquivos de texto, segmentando-as
 /// por modelo de documento fiscal e limitando a quantidade de linhas por arquivo.
 ///
 /// ### Argumentos
-/// * `chaves` - Um `HashSet` contendo as chaves de acesso (strings de 44 caracteres).
+/// * `chaves` - Um `HashSet` contendo as chaves de acesso de 44 dígitos.
 /// * `target_base` - O caminho base (prefixo) onde os arquivos serão gerados.
 ///
 /// ### Erros
@@ -871,7 +1715,10 @@ pub fn imprimir_chaves_nao_encontradas(
 /// ### Exemplo de Saída
 /// Se o target for `/tmp/falta`, gera arquivos como:
 /// `/tmp/falta-NFe-000000.txt`, `/tmp/falta-NFe-000900.txt`, etc.
-pub fn exportar_chaves_faltantes(chaves: &HashSet<String>, target_base: &Path) -> SpedResult<()> {
+pub fn exportar_chaves_faltantes(
+    chaves: &HashSet<ChaveAcesso>,
+    target_base: &Path,
+) -> SpedResult<()> {
     // Definimos o limite de linhas em um arquivo
     const MAX_LINHAS: usize = 900;
 
@@ -880,25 +1727,22 @@ pub fn exportar_chaves_faltantes(chaves: &HashSet<String>, target_base: &Path) -
     }
 
     // --- 1. PREPARAÇÃO E ORDENAÇÃO ---
-    // Filtramos chaves válidas e coletamos referências para evitar clonagem desnecessária de strings.
-    // Usamos sort_unstable_by_key por ser mais rápido que o sort estável original.
-    let mut sorted_chaves: Vec<&String> = chaves.iter().filter(|c| c.len() >= 22).collect();
+    // A ordenação da forma compacta coincide com a ordenação alfabética da chave.
+    // Usamos sort_by_cached_key para extrair o modelo de cada chave uma única vez.
+    let mut sorted_chaves: Vec<&ChaveAcesso> = chaves.iter().collect();
 
-    sorted_chaves.sort_unstable_by_key(|&c| {
+    sorted_chaves.sort_by_cached_key(|&c| {
         (
-            &c[20..22], // Agrupa por Modelo (NFe 55, CTe 57, etc)
-            c,          // Ordena pela chave completa dentro do grupo
+            c.modelo(), // Agrupa por Modelo (NFe 55, CTe 57, etc)
+            *c,         // Ordena pela chave completa dentro do grupo
         )
     });
 
     // --- 2. PROCESSAMENTO POR GRUPOS (MODELOS) ---
     // chunk_by separa as chaves toda vez que o modelo (pos 20-22) muda.
-    for grupo_modelo in sorted_chaves.chunk_by(|a, b| a[20..22] == b[20..22]) {
-        // Extraímos o código do modelo do primeiro elemento do grupo
-        let modelo_cod = &grupo_modelo[0][20..22];
-
-        // Obtemos o nome descritivo e limpamos caracteres problemáticos (como o ':')
-        let doc_nome = get_modelo_documentos_fiscais(modelo_cod); // .replace(':', "-");
+    for grupo_modelo in sorted_chaves.chunk_by(|a, b| a.modelo() == b.modelo()) {
+        // Obtemos o nome descritivo a partir do modelo do primeiro elemento do grupo
+        let doc_nome = grupo_modelo[0].modelo().descricao();
 
         // --- 3. DIVISÃO EM CHUNKS (ARQUIVOS) ---
         // Para cada modelo, dividimos as chaves em blocos de no máximo 900 linhas.
@@ -927,3 +1771,218 @@ pub fn exportar_chaves_faltantes(chaves: &HashSet<String>, target_base: &Path) -
 
     Ok(())
 }
+
+/// Imprime e exporta a origem (declarada ou correlacionada) das chaves não encontradas.
+pub fn exportar_origem_das_chaves_faltantes(
+    chaves: &HashSet<ChaveAcesso>,
+    info_efd: &InfoEfd,
+    target_base: &Path,
+) -> SpedResult<()> {
+    let mut sorted_chaves: Vec<(ChaveAcesso, Motivo)> = chaves
+        .iter()
+        .filter_map(|chave| Some((*chave, info_efd.motivo(chave)?)))
+        .collect();
+
+    // Chaves declaradas primeiro; depois por modelo e chave
+    sorted_chaves
+        .sort_by_cached_key(|(chave, motivo)| (!motivo.eh_declarada(), chave.modelo(), *chave));
+
+    let declaradas = sorted_chaves
+        .iter()
+        .filter(|(_, motivo)| motivo.eh_declarada())
+        .count();
+
+    println!(
+        " Chaves NÃO encontradas declaradas na EFD: {} ; correlacionadas (CTes/NFes): {}\n",
+        fmt_milhares(declaradas),
+        fmt_milhares(sorted_chaves.len() - declaradas)
+    );
+
+    if sorted_chaves.is_empty() {
+        return Ok(());
+    }
+
+    let file_path = format!(
+        "{}-Origem das Chaves não Encontradas.csv",
+        target_base.display()
+    );
+
+    println!(
+        " ---> Novo arquivo de origem das chaves não encontradas: <{}>\n",
+        file_path
+    );
+
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(BufWriter::new(File::create(&file_path)?));
+
+    wtr.write_record(["Chave", "Modelo", "Origem", "Motivo"])?;
+
+    for (chave, motivo) in sorted_chaves {
+        let origem = if motivo.eh_declarada() {
+            "Declarada na EFD"
+        } else {
+            "Correlacionada"
+        };
+
+        wtr.write_record([
+            chave.to_string(),
+            chave.modelo().to_string(),
+            origem.to_string(),
+            motivo.to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Imprime e exporta as chaves não encontradas por arquivo da EFD e período de apuração.
+pub fn exportar_chaves_faltantes_por_arquivo(
+    chaves: &HashSet<ChaveAcesso>,
+    info_efd: &InfoEfd,
+    arquivos_efd: &[ArquivoEfd],
+    target_base: &Path,
+) -> SpedResult<()> {
+    let ordem: HashMap<&Path, usize> = arquivos_efd
+        .iter()
+        .enumerate()
+        .map(|(i, arquivo)| (arquivo.path.as_path(), i))
+        .collect();
+
+    // Por arquivo: (chaves do filtro, chaves não encontradas)
+    let mut totais = vec![(0_usize, 0_usize); arquivos_efd.len()];
+    let mut faltantes: Vec<(&OrigemEfd, ChaveAcesso)> = Vec::new();
+
+    for (chave, origens) in &info_efd.origens {
+        if !info_efd.chaves.contains(chave) {
+            continue;
+        }
+
+        let nao_encontrada = chaves.contains(chave);
+        let indices: BTreeSet<usize> = origens
+            .iter()
+            .filter_map(|origem| ordem.get(origem.arquivo.as_path()).copied())
+            .collect();
+
+        for i in indices {
+            totais[i].0 += 1;
+            totais[i].1 += usize::from(nao_encontrada);
+        }
+
+        // A declaração prevalece sobre a correlação no mesmo arquivo e período
+        if nao_encontrada {
+            faltantes.extend(
+                origens
+                    .iter()
+                    .filter(|origem| {
+                        origem.motivo.eh_declarada()
+                            || !origens.contains(&OrigemEfd {
+                                motivo: Motivo::Declarada,
+                                ..(*origem).clone()
+                            })
+                    })
+                    .map(|origem| (origem, *chave)),
+            );
+        }
+    }
+
+    println!(" Chaves NÃO encontradas por arquivo da EFD:\n");
+    for (i, (arquivo, (total, nao_encontradas))) in arquivos_efd.iter().zip(&totais).enumerate() {
+        println!(
+            "{:6}: {} de {} chaves <{}>",
+            i + 1,
+            fmt_milhares(*nao_encontradas),
+            fmt_milhares(*total),
+            arquivo.path.display()
+        );
+    }
+    println!();
+
+    if faltantes.is_empty() {
+        return Ok(());
+    }
+
+    // Ordenação pela ordem dos arquivos, período de apuração, modelo e chave
+    faltantes.sort_by_cached_key(|(origem, chave)| {
+        (
+            ordem.get(origem.arquivo.as_path()).copied(),
+            AnoMes::parse(&origem.periodo_apuracao),
+            chave.modelo(),
+            *chave,
+        )
+    });
+
+    let file_path = format!(
+        "{}-Chaves não Encontradas por Arquivo da EFD.csv",
+        target_base.display()
+    );
+
+    println!(
+        " ---> Novo arquivo de chaves não encontradas por arquivo da EFD: <{}>\n",
+        file_path
+    );
+
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(BufWriter::new(File::create(&file_path)?));
+
+    wtr.write_record([
+        "Arquivo da EFD",
+        "Período de Apuração",
+        "Chave",
+        "Modelo",
+        "Motivo",
+    ])?;
+
+    for (origem, chave) in faltantes {
+        wtr.write_record([
+            origem.arquivo.display().to_string(),
+            origem.periodo_apuracao.clone(),
+            chave.to_string(),
+            chave.modelo().to_string(),
+            origem.motivo.to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Exporta as chaves com DV inválido, com o arquivo e a linha de cada ocorrência, em `<target>-Chaves com DV inválido.csv`.
+pub fn exportar_chaves_invalidas(chaves: &[ChaveInvalida], target_base: &Path) -> SpedResult<()> {
+    if chaves.is_empty() {
+        return Ok(());
+    }
+
+    // Ordenação por arquivo, linha e chave para facilitar a conferência
+    let mut sorted_chaves: Vec<&ChaveInvalida> = chaves.iter().collect();
+    sorted_chaves.sort_unstable_by_key(|c| (&c.arquivo, c.linha, c.chave));
+
+    let file_path = format!("{}-Chaves com DV inválido.csv", target_base.display());
+
+    println!(
+        " ---> Novo arquivo de chaves com DV inválido: <{}> ({} ocorrências)",
+        file_path,
+        fmt_milhares(chaves.len())
+    );
+
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(BufWriter::new(File::create(&file_path)?));
+
+    wtr.write_record(["Chave", "DV Informado", "DV Calculado", "Arquivo", "Linha"])?;
+
+    for invalida in sorted_chaves {
+        wtr.write_record([
+            &invalida.chave.to_string(),
+            &invalida.chave.dv().to_string(),
+            &invalida.chave.calcular_dv().to_string(),
+            &invalida.arquivo.display().to_string(),
+            &invalida.linha.to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
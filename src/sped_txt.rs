@@ -0,0 +1,638 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ArquivoEfd, COLUNAS_EFD, Codificacao, ORDEM_COLUNAS_EFD, SpedResult, TipoDeArquivo,
+    abrir_decodificado, fmt_milhares,
+};
+
+/// Linha da EFD Contribuições no layout de `COLUNAS_EFD` (chave lógica -> valor).
+pub type LinhaEfd = HashMap<&'static str, String>;
+
+/// Participante informado no registro 0150.
+#[derive(Debug, Default, Clone)]
+struct Participante {
+    nome: String,
+    cnpj: String,
+    cpf: String,
+}
+
+/// Documento fiscal em leitura (registro pai e seus registros filhos).
+#[derive(Debug, Default)]
+struct Documento {
+    /// Campos comuns a todas as linhas do documento.
+    comuns: LinhaEfd,
+    /// Valores totais do documento (usados apenas se não houver itens).
+    totais: LinhaEfd,
+    /// Itens do registro C170.
+    itens: Vec<LinhaEfd>,
+    /// Registros D101 (PIS) e D105 (COFINS): (nº da linha, campos).
+    pis: Vec<(usize, Vec<String>)>,
+    cofins: Vec<(usize, Vec<String>)>,
+}
+
+/// Conversor do arquivo original da EFD Contribuições (SPED `.txt`, delimitado por '|')
+/// para as linhas do layout `COLUNAS_EFD`.
+///
+/// São lidos os registros A100, C100/C170, C500 e D100/D101/D105, com o contexto
+/// do contribuinte (0000), dos estabelecimentos (0140, A010, C010 e D010) e dos
+/// participantes (0150).
+///
+/// O tipo de crédito não é preenchido, e a natureza da base de cálculo é preenchida
+/// apenas nas linhas D101/D105: os filtros `--tipo-de-credito` e `--natureza-bc`
+/// não são aceitos com o arquivo SPED (ver `FiltroEfd::disponivel_no_sped_txt`).
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::ConversorEfd;
+///
+/// let sped = "\
+/// |0000|006|0|||01012024|31012024|EMPRESA TESTE|11111111000111|SP|3550308||00|0|
+/// |0150|P1|FORNECEDOR|1058|12345678000190||||3550308|||||
+/// |C010|11111111000111|2|
+/// |C100|0|1|P1|55|00|001|1|35240112345678000190550010000000011000000010|15012024|20012024|100,00|0|0|0|100,00|0|0|0|0|0|0|0|0|0|1,65|7,60|0|0|
+/// |C170|1|P01|PRODUTO|1|UN|100,00|0|0|000|1102|1|0|0|0|0|0|0|0|0|0|0|0|0|50|100,00|1,65|||1,65|50|100,00|7,60|||7,60|CONTA|
+/// |9999|6|
+/// ";
+///
+/// let mut conversor = ConversorEfd::new("efd.txt");
+/// let mut linhas = Vec::new();
+/// for (i, registro) in sped.lines().enumerate() {
+///     linhas.extend(conversor.processar_registro(i + 1, registro));
+/// }
+/// linhas.extend(conversor.finalizar());
+///
+/// assert_eq!(linhas.len(), 1);
+/// assert_eq!(linhas[0]["registro_bloco"], "C170");
+/// assert_eq!(linhas[0]["chave_documento"], "35240112345678000190550010000000011000000010");
+/// assert_eq!(linhas[0]["cnpj_participante"], "12.345.678/0001-90");
+/// assert_eq!(linhas[0]["periodo_apuracao"], "01/2024");
+/// assert_eq!(linhas[0]["data_emissao_nota"], "15/01/2024");
+/// assert_eq!(linhas[0]["codigo_cst"], "50");
+/// ```
+#[derive(Debug, Default)]
+pub struct ConversorEfd {
+    /// Nome do arquivo da EFD (coluna `efd_file`).
+    arquivo: String,
+    /// Contribuinte e período de apuração (registro 0000).
+    contexto: LinhaEfd,
+    /// CNPJ do estabelecimento corrente (0140, A010, C010 ou D010).
+    estabelecimento: String,
+    /// Participantes (0150) por (CNPJ do estabelecimento do 0140, COD_PART):
+    /// o mesmo código pode designar participantes distintos em cada estabelecimento.
+    participantes: HashMap<(String, String), Participante>,
+    documento: Option<Documento>,
+    num_linhas: usize,
+}
+
+impl ConversorEfd {
+    pub fn new(arquivo: &str) -> Self {
+        ConversorEfd {
+            arquivo: arquivo.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Processa um registro (linha) do arquivo SPED.
+    ///
+    /// Retorna as linhas do documento anterior, concluído ao se iniciar outro
+    /// documento, outro estabelecimento ou o encerramento do bloco.
+    pub fn processar_registro(&mut self, num_linha: usize, registro: &str) -> Vec<LinhaEfd> {
+        let registro = registro.trim_end_matches(['\r', '\n']);
+        let Some(conteudo) = registro.strip_prefix('|') else {
+            return Vec::new();
+        };
+
+        let campos: Vec<&str> = conteudo.split('|').map(str::trim).collect();
+        let campo = |i: usize| campos.get(i).copied().unwrap_or_default().to_string();
+        let reg = campos[0];
+
+        match reg {
+            "0000" => {
+                self.definir_periodo(&campo(5));
+                self.contexto.insert("nome_contribuinte", campo(7));
+                self.estabelecimento = campo(8);
+                Vec::new()
+            }
+            "0140" => {
+                self.estabelecimento = campo(3);
+                Vec::new()
+            }
+            "0150" => {
+                let participante = Participante {
+                    nome: campo(2),
+                    cnpj: campo(4),
+                    cpf: campo(5),
+                };
+                self.participantes
+                    .insert((self.estabelecimento.clone(), campo(1)), participante);
+                Vec::new()
+            }
+            "A010" | "C010" | "D010" => {
+                let linhas = self.finalizar();
+                self.estabelecimento = campo(1);
+                linhas
+            }
+            "A100" => {
+                let linhas = self.finalizar();
+                let mut doc = self.iniciar_documento(reg, num_linha, &campo(3));
+                doc.comuns.extend([
+                    ("tipo_de_operacao", tipo_de_operacao(&campo(1))),
+                    ("num_doc_fiscal", campo(7)),
+                    ("chave_documento", campo(8)),
+                    ("data_emissao_nota", formatar_data_sped(&campo(9))),
+                    ("data_lancamento", formatar_data_sped(&campo(10))),
+                ]);
+                doc.totais.extend([
+                    ("valor_do_item", campo(11)),
+                    ("valor_bc_contrib", campo(14)),
+                    ("valor_de_pis", campo(15)),
+                    ("valor_de_cofins", campo(17)),
+                    ("valor_de_iss", campo(20)),
+                ]);
+                self.documento = Some(doc);
+                linhas
+            }
+            "C100" => {
+                let linhas = self.finalizar();
+                let mut doc = self.iniciar_documento(reg, num_linha, &campo(3));
+                doc.comuns.extend([
+                    ("tipo_de_operacao", tipo_de_operacao(&campo(1))),
+                    ("modelo_doc_fiscal", campo(4)),
+                    ("num_doc_fiscal", campo(7)),
+                    ("chave_documento", campo(8)),
+                    ("data_emissao_nota", formatar_data_sped(&campo(9))),
+                    ("data_lancamento", formatar_data_sped(&campo(10))),
+                ]);
+                doc.totais.extend([
+                    ("valor_do_item", campo(11)),
+                    ("valor_bc_icms", campo(20)),
+                    ("valor_de_icms", campo(21)),
+                    ("valor_de_pis", campo(25)),
+                    ("valor_de_cofins", campo(26)),
+                ]);
+                self.documento = Some(doc);
+                linhas
+            }
+            "C170" => {
+                if let Some(doc) = &mut self.documento
+                    && doc
+                        .comuns
+                        .get("registro_bloco")
+                        .is_some_and(|r| r == "C100")
+                {
+                    doc.itens.push(LinhaEfd::from([
+                        ("registro_bloco", reg.to_string()),
+                        ("efd_line", num_linha.to_string()),
+                        ("num_item", campo(1)),
+                        ("descricao_do_item", campo(3)),
+                        ("valor_do_item", campo(6)),
+                        ("codigo_cfop", campo(10)),
+                        ("valor_bc_icms", campo(12)),
+                        ("aliq_icms", campo(13)),
+                        ("valor_de_icms", campo(14)),
+                        ("codigo_cst", campo(24)),
+                        ("valor_bc_contrib", campo(25)),
+                        ("aliq_pis", campo(26)),
+                        ("valor_de_pis", campo(29)),
+                        ("aliq_cofins", campo(32)),
+                        ("valor_de_cofins", campo(35)),
+                        ("escrituracao_contabil", campo(36)),
+                    ]));
+                }
+                Vec::new()
+            }
+            "C500" => {
+                let linhas = self.finalizar();
+                let mut doc = self.iniciar_documento(reg, num_linha, &campo(1));
+                doc.comuns.extend([
+                    ("tipo_de_operacao", "Entrada".to_string()),
+                    ("modelo_doc_fiscal", campo(2)),
+                    ("num_doc_fiscal", campo(6)),
+                    ("data_emissao_nota", formatar_data_sped(&campo(7))),
+                    ("data_lancamento", formatar_data_sped(&campo(8))),
+                    ("chave_documento", campo(14)),
+                ]);
+                doc.totais.extend([
+                    ("valor_do_item", campo(9)),
+                    ("valor_de_icms", campo(10)),
+                    ("valor_de_pis", campo(12)),
+                    ("valor_de_cofins", campo(13)),
+                ]);
+                self.documento = Some(doc);
+                linhas
+            }
+            "D100" => {
+                let linhas = self.finalizar();
+                let mut doc = self.iniciar_documento(reg, num_linha, &campo(3));
+                doc.comuns.extend([
+                    ("tipo_de_operacao", tipo_de_operacao(&campo(1))),
+                    ("modelo_doc_fiscal", campo(4)),
+                    ("num_doc_fiscal", campo(8)),
+                    ("chave_documento", campo(9)),
+                    ("data_emissao_nota", formatar_data_sped(&campo(10))),
+                    ("data_lancamento", formatar_data_sped(&campo(11))),
+                ]);
+                doc.totais.insert("valor_do_item", campo(14));
+                self.documento = Some(doc);
+                linhas
+            }
+            "D101" | "D105" => {
+                if let Some(doc) = &mut self.documento
+                    && doc
+                        .comuns
+                        .get("registro_bloco")
+                        .is_some_and(|r| r == "D100")
+                {
+                    let valores = campos.iter().map(|c| c.to_string()).collect();
+                    match reg {
+                        "D101" => doc.pis.push((num_linha, valores)),
+                        _ => doc.cofins.push((num_linha, valores)),
+                    }
+                }
+                Vec::new()
+            }
+            // Encerramento de bloco (A990, C990, D990, ...) ou do arquivo
+            r if r.ends_with("990") || r == "9999" => self.finalizar(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Conclui o documento pendente, retornando suas linhas.
+    pub fn finalizar(&mut self) -> Vec<LinhaEfd> {
+        let Some(mut doc) = self.documento.take() else {
+            return Vec::new();
+        };
+
+        // D100: os registros D101 (PIS) e D105 (COFINS) são pareados pela ordem
+        for i in 0..doc.pis.len().max(doc.cofins.len()) {
+            let pis = doc.pis.get(i);
+            let cofins = doc.cofins.get(i);
+            let (linha, reg) = match (pis, cofins) {
+                (Some((linha, _)), _) => (*linha, "D101"),
+                (None, Some((linha, _))) => (*linha, "D105"),
+                (None, None) => unreachable!(),
+            };
+            let campo = |registro: Option<&(usize, Vec<String>)>, j: usize| {
+                registro
+                    .and_then(|(_, campos)| campos.get(j).cloned())
+                    .unwrap_or_default()
+            };
+            let principal = pis.or(cofins);
+
+            doc.itens.push(LinhaEfd::from([
+                ("registro_bloco", reg.to_string()),
+                ("efd_line", linha.to_string()),
+                ("valor_do_item", campo(principal, 2)),
+                ("codigo_cst", campo(principal, 3)),
+                ("natureza_bc", campo(principal, 4)),
+                ("valor_bc_contrib", campo(principal, 5)),
+                ("aliq_pis", campo(pis, 6)),
+                ("valor_de_pis", campo(pis, 7)),
+                ("aliq_cofins", campo(cofins, 6)),
+                ("valor_de_cofins", campo(cofins, 7)),
+                ("escrituracao_contabil", campo(principal, 8)),
+            ]));
+        }
+
+        // Documento sem itens: uma única linha com os valores totais
+        let itens = if doc.itens.is_empty() {
+            vec![std::mem::take(&mut doc.totais)]
+        } else {
+            std::mem::take(&mut doc.itens)
+        };
+
+        itens
+            .into_iter()
+            .map(|item| {
+                self.num_linhas += 1;
+                let mut linha = doc.comuns.clone();
+                linha.extend(item);
+                linha.insert("num_linha", self.num_linhas.to_string());
+                linha
+            })
+            .collect()
+    }
+
+    fn definir_periodo(&mut self, dt_ini: &str) {
+        let (Some(mes), Some(ano)) = (dt_ini.get(2..4), dt_ini.get(4..8)) else {
+            return;
+        };
+        let num_mes: u32 = mes.parse().unwrap_or_default();
+
+        self.contexto.extend([
+            ("periodo_apuracao", format!("{mes}/{ano}")),
+            ("periodo_apuracao_ano", ano.to_string()),
+            (
+                "periodo_apuracao_tri",
+                (num_mes.saturating_sub(1) / 3 + 1).to_string(),
+            ),
+            ("periodo_apuracao_mes", num_mes.to_string()),
+        ]);
+    }
+
+    fn iniciar_documento(&self, reg: &str, num_linha: usize, cod_part: &str) -> Documento {
+        let participante = self
+            .participantes
+            .get(&(self.estabelecimento.clone(), cod_part.to_string()))
+            .cloned()
+            .unwrap_or_default();
+
+        let mut comuns = self.contexto.clone();
+        comuns.extend([
+            ("efd_file", self.arquivo.clone()),
+            ("efd_line", num_linha.to_string()),
+            ("registro_bloco", reg.to_string()),
+            ("cnpj_contribuinte", formatar_cnpj(&self.estabelecimento)),
+            ("cnpj_participante", formatar_cnpj(&participante.cnpj)),
+            ("cpf_participante", formatar_cpf(&participante.cpf)),
+            ("nome_participante", participante.nome),
+        ]);
+
+        Documento {
+            comuns,
+            ..Default::default()
+        }
+    }
+}
+
+/// Indicador do tipo de operação (IND_OPER): 0 - Entrada; 1 - Saída.
+fn tipo_de_operacao(ind_oper: &str) -> String {
+    match ind_oper {
+        "0" => "Entrada",
+        "1" => "Saída",
+        outro => outro,
+    }
+    .to_string()
+}
+
+/// Converte `DDMMAAAA` em `DD/MM/AAAA`.
+fn formatar_data_sped(data: &str) -> String {
+    match (data.get(..2), data.get(2..4), data.get(4..)) {
+        (Some(dia), Some(mes), Some(ano)) if data.len() == 8 => format!("{dia}/{mes}/{ano}"),
+        _ => data.to_string(),
+    }
+}
+
+/// Converte `11111111000111` em `11.111.111/0001-11`.
+fn formatar_cnpj(cnpj: &str) -> String {
+    if cnpj.len() != 14 || !cnpj.is_ascii() {
+        return cnpj.to_string();
+    }
+    format!(
+        "{}.{}.{}/{}-{}",
+        &cnpj[..2],
+        &cnpj[2..5],
+        &cnpj[5..8],
+        &cnpj[8..12],
+        &cnpj[12..]
+    )
+}
+
+/// Converte `12345678901` em `123.456.789-01`.
+fn formatar_cpf(cpf: &str) -> String {
+    if cpf.len() != 11 || !cpf.is_ascii() {
+        return cpf.to_string();
+    }
+    format!("{}.{}.{}-{}", &cpf[..3], &cpf[3..6], &cpf[6..9], &cpf[9..])
+}
+
+/// Verifica se o arquivo é o SPED original da EFD (primeiro registro `|0000|`).
+pub fn eh_sped_efd_txt(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+
+    let mut primeira = Vec::new();
+    BufReader::new(file)
+        .read_until(b'\n', &mut primeira)
+        .is_ok_and(|_| {
+            String::from_utf8_lossy(&primeira)
+                .trim_start_matches('\u{feff}')
+                .starts_with("|0000|")
+        })
+}
+
+/// Substitui os arquivos SPED `.txt` da EFD Contribuições pelos arquivos CSV convertidos,
+/// lidos em seguida como UTF-8 (codificação de gravação de `converter_efd_txt`).
+pub fn converter_arquivos_efd_txt(
+    arquivos: &mut [ArquivoEfd],
+    target_base: &Path,
+) -> SpedResult<()> {
+    for arquivo in arquivos {
+        if let TipoDeArquivo::EFDContribTxt = arquivo.tipo {
+            arquivo.path = converter_efd_txt(&arquivo.path, arquivo.codificacao, target_base)?;
+            arquivo.tipo = TipoDeArquivo::EFDContrib;
+            arquivo.codificacao = Codificacao::Utf8;
+        }
+    }
+
+    Ok(())
+}
+
+/// Converte o SPED `.txt` da EFD Contribuições em `<target>-EFD Contribuições (<nome>).csv`.
+pub fn converter_efd_txt(
+    origem: &Path,
+    codificacao: Codificacao,
+    target_base: &Path,
+) -> SpedResult<PathBuf> {
+    let file = abrir_decodificado(origem, codificacao)?;
+
+    let nome_arquivo = origem
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let nome_base = origem
+        .file_stem()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let file_path = PathBuf::from(format!(
+        "{}-EFD Contribuições ({}).csv",
+        target_base.display(),
+        nome_base
+    ));
+
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(b'|')
+        .from_writer(BufWriter::new(File::create(&file_path)?));
+
+    wtr.write_record(ORDEM_COLUNAS_EFD.map(|coluna| COLUNAS_EFD[coluna]))?;
+
+    let mut conversor = ConversorEfd::new(&nome_arquivo);
+    let mut reader = BufReader::with_capacity(128 * 1024, file);
+    let mut registro = String::new();
+    let mut num_linha = 0;
+    let mut num_registros = 0;
+
+    let mut gravar = |linhas: Vec<LinhaEfd>| -> SpedResult<()> {
+        for linha in linhas {
+            num_registros += 1;
+            wtr.write_record(
+                ORDEM_COLUNAS_EFD.map(|coluna| linha.get(coluna).map_or("", String::as_str)),
+            )?;
+        }
+        Ok(())
+    };
+
+    // Arquivos SPED são usualmente codificados em Latin-1: o leitor transcodifica para UTF-8
+    loop {
+        registro.clear();
+        if reader.read_line(&mut registro)? == 0 {
+            break;
+        }
+        num_linha += 1;
+
+        gravar(conversor.processar_registro(num_linha, &registro))?;
+    }
+    gravar(conversor.finalizar())?;
+
+    wtr.flush()?;
+
+    println!(
+        " Arquivo SPED da EFD Contribuições <{}>: {} registros e {} linhas de documentos.",
+        origem.display(),
+        fmt_milhares(num_linha),
+        fmt_milhares(num_registros)
+    );
+    println!(
+        " ---> Novo arquivo da EFD Contribuições convertida: <{}>\n",
+        file_path.display()
+    );
+
+    Ok(file_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, get_efd_info};
+
+    /// Converte os registros, na ordem, em linhas do layout `COLUNAS_EFD`.
+    fn converter(sped: &str) -> Vec<LinhaEfd> {
+        let mut conversor = ConversorEfd::new("efd.txt");
+        let mut linhas = Vec::new();
+        for (i, registro) in sped.lines().enumerate() {
+            linhas.extend(conversor.processar_registro(i + 1, registro));
+        }
+        linhas.extend(conversor.finalizar());
+        linhas
+    }
+
+    #[test]
+    fn documento_sem_itens_usa_os_valores_totais() {
+        let linhas = converter(
+            "\
+|0000|006|0|||01032024|31032024|EMPRESA|11111111000111|SP|3550308||00|0|
+|C010|11111111000111|2|
+|C100|0|1|P1|55|00|001|1|35240112345678000190550010000012341123456786|05032024|06032024|250,00|0|0|0|250,00|0|0|0|0|0|0|0|0|0|4,13|19,00|0|0|
+|C990|4|
+",
+        );
+
+        assert_eq!(linhas.len(), 1);
+        assert_eq!(linhas[0]["registro_bloco"], "C100");
+        assert_eq!(linhas[0]["efd_line"], "3");
+        assert_eq!(linhas[0]["valor_do_item"], "250,00");
+        assert_eq!(linhas[0]["valor_de_pis"], "4,13");
+        assert_eq!(linhas[0]["valor_de_cofins"], "19,00");
+        assert_eq!(linhas[0]["periodo_apuracao_tri"], "1");
+        assert_eq!(linhas[0]["data_lancamento"], "06/03/2024");
+    }
+
+    #[test]
+    fn registros_d101_e_d105_sao_pareados_pela_ordem() {
+        let linhas = converter(
+            "\
+|0000|006|0|||01012024|31012024|EMPRESA|11111111000111|SP|3550308||00|0|
+|D010|11111111000111|
+|D100|0|1|P1|57|00|1||10|35240112345678000190570010000000011000000017|10012024|12012024|0|||500,00|0|0|500,00|0|0|0|0|
+|D101|0|500,00|50|03|500,00|1,65|8,25|CONTA|
+|D105|0|500,00|50|03|500,00|7,60|38,00|CONTA|
+|D990|5|
+",
+        );
+
+        assert_eq!(linhas.len(), 1);
+        assert_eq!(linhas[0]["registro_bloco"], "D101");
+        assert_eq!(linhas[0]["efd_line"], "4");
+        assert_eq!(linhas[0]["natureza_bc"], "03");
+        assert_eq!(linhas[0]["aliq_pis"], "1,65");
+        assert_eq!(linhas[0]["valor_de_pis"], "8,25");
+        assert_eq!(linhas[0]["aliq_cofins"], "7,60");
+        assert_eq!(linhas[0]["valor_de_cofins"], "38,00");
+        assert_eq!(linhas[0]["num_doc_fiscal"], "10");
+    }
+
+    #[test]
+    fn participantes_sao_distintos_em_cada_estabelecimento() {
+        let linhas = converter(
+            "\
+|0000|006|0|||01012024|31012024|EMPRESA|11111111000111|SP|3550308||00|0|
+|0140||MATRIZ|11111111000111|
+|0150|P1|FORNECEDOR A|1058|22222222000122||||3550308|||||
+|0140||FILIAL|11111111000202|
+|0150|P1|FORNECEDOR B|1058|33333333000133||||3550308|||||
+|C010|11111111000202|2|
+|C100|0|1|P1|55|00|001|1|35240112345678000190550010000012341123456786|15012024|20012024|100,00|0|0|0|100,00|0|0|0|0|0|0|0|0|0|1,65|7,60|0|0|
+|C990|3|
+",
+        );
+
+        assert_eq!(linhas.len(), 1);
+        assert_eq!(linhas[0]["cnpj_contribuinte"], "11.111.111/0002-02");
+        assert_eq!(linhas[0]["nome_participante"], "FORNECEDOR B");
+        assert_eq!(linhas[0]["cnpj_participante"], "33.333.333/0001-33");
+    }
+
+    #[test]
+    fn arquivo_sped_em_latin1_e_convertido_e_lido_em_utf8() -> SpedResult<()> {
+        let dir = std::env::temp_dir().join(format!("sped_txt_latin1_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+
+        let sped = "\
+|0000|006|0|||01012024|31012024|AÇÚCAR E CAFÉ LTDA|11111111000111|SP|3550308||00|0|
+|0150|P1|FORNECEDOR SÃO JOÃO|1058|12345678000190||||3550308|||||
+|C010|11111111000111|2|
+|C100|0|1|P1|55|00|001|1|35240112345678000190550010000012341123456786|15012024|20012024|100,00|0|0|0|100,00|0|0|0|0|0|0|0|0|0|1,65|7,60|0|0|
+|9999|5|
+";
+        let origem = dir.join("efd.txt");
+        std::fs::write(&origem, encoding_rs::WINDOWS_1252.encode(sped).0)?;
+
+        let mut arquivos = vec![ArquivoEfd {
+            path: origem,
+            tipo: TipoDeArquivo::EFDContribTxt,
+            codificacao: Codificacao::Latin1,
+        }];
+        converter_arquivos_efd_txt(&mut arquivos, &dir.join("info.csv"))?;
+
+        assert!(matches!(arquivos[0].tipo, TipoDeArquivo::EFDContrib));
+        assert_eq!(arquivos[0].codificacao, Codificacao::Utf8);
+
+        let convertido = std::fs::read_to_string(&arquivos[0].path)?;
+        assert!(convertido.contains("AÇÚCAR E CAFÉ LTDA"));
+        assert!(convertido.contains("FORNECEDOR SÃO JOÃO"));
+
+        // A codificação informada (Latin-1) se aplica apenas ao arquivo SPED original
+        let config = Config {
+            arquivos_efd: arquivos,
+            codificacao: Codificacao::Latin1,
+            ..Config::default()
+        };
+        let info = get_efd_info(&config)?;
+
+        let chave = "35240112345678000190550010000012341123456786"
+            .parse()
+            .unwrap();
+        assert!(info.declaradas.contains(&chave));
+        assert_eq!(info.linhas_lidas, 1);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+use rayon::prelude::*;
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs::{self, File},
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ChaveAcesso, ChaveInvalida, Codificacao, KeyMap, SpedError, SpedResult, decodificar_xml,
+    fmt_milhares,
+};
+
+/// Relações entre chaves extraídas dos arquivos XML de CT-e.
+#[derive(Debug, Default)]
+pub struct RelacoesCte {
+    /// CTe -> NFes transportadas (`infNFe/chave`).
+    pub cte_nfes: KeyMap,
+    /// CTe -> CTe complementar (`infCteComp/chCTe`), cada par uma única vez.
+    pub cte_complementar: KeyMap,
+    /// Ocorrências de chaves com DV inválido.
+    pub invalidas: Vec<ChaveInvalida>,
+    /// Número de documentos CT-e lidos.
+    pub documentos: usize,
+}
+
+impl RelacoesCte {
+    fn merge(mut self, other: RelacoesCte) -> RelacoesCte {
+        for (key, values) in other.cte_nfes {
+            self.cte_nfes.entry(key).or_default().extend(values);
+        }
+        for (key, values) in other.cte_complementar {
+            self.cte_complementar.entry(key).or_default().extend(values);
+        }
+        self.invalidas.extend(other.invalidas);
+        self.documentos += other.documentos;
+        self
+    }
+}
+
+/// Constrói as relações `cte_nfes` e `cte_complementar` a partir dos arquivos XML
+/// de CT-e (`CTe` ou `cteProc`) contidos no diretório e em seus subdiretórios.
+///
+/// Arquivos `.zip` são lidos diretamente: cada arquivo `.xml` do lote é analisado.
+/// Arquivos XML de outros documentos (ex: NF-e) são ignorados.
+pub fn ler_relacoes_dos_xmls_de_cte(
+    dir: &Path,
+    codificacao: Codificacao,
+) -> SpedResult<RelacoesCte> {
+    let arquivos = buscar_arquivos_xml_e_zip(dir)?;
+
+    let relacoes = arquivos
+        .par_iter()
+        .map(|path| {
+            ler_arquivo_xml_ou_zip(path, codificacao)
+                .map_err(|e| {
+                    eprintln!(" [ERRO] Arquivo <{}>: {}", path.display(), e);
+                    e
+                })
+                .unwrap_or_default() // Se falhar, retorna resultado vazio
+        })
+        .reduce(RelacoesCte::default, RelacoesCte::merge);
+
+    println!(
+        "Encontrado {:>6} CTes em {:>6} arquivos XML/ZIP do diretório <{}>: {} CTes com NFes e {} CTes com CTes Complementares.",
+        fmt_milhares(relacoes.documentos),
+        fmt_milhares(arquivos.len()),
+        dir.display(),
+        fmt_milhares(relacoes.cte_nfes.len()),
+        fmt_milhares(relacoes.cte_complementar.len()),
+    );
+
+    Ok(relacoes)
+}
+
+/// Busca recursiva dos arquivos com extensão `.xml` ou `.zip`.
+pub fn buscar_arquivos_xml_e_zip(dir: &Path) -> SpedResult<Vec<PathBuf>> {
+    let mut arquivos = Vec::new();
+
+    for entry in fs::read_dir(dir).map_err(|e| SpedError::IoReader {
+        source: e,
+        arquivo: dir.to_path_buf(),
+    })? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            arquivos.extend(buscar_arquivos_xml_e_zip(&path)?);
+        } else if tem_extensao(&path, "xml") || tem_extensao(&path, "zip") {
+            arquivos.push(path);
+        }
+    }
+
+    arquivos.sort();
+    Ok(arquivos)
+}
+
+fn tem_extensao(path: &Path, extensao: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extensao))
+}
+
+fn ler_arquivo_xml_ou_zip(path: &Path, codificacao: Codificacao) -> SpedResult<RelacoesCte> {
+    let mut relacoes = RelacoesCte::default();
+
+    percorrer_conteudos_xml(path, codificacao, |origem, xml| {
+        relacoes = std::mem::take(&mut relacoes).merge(analisar_xml_de_cte(&xml, &origem)?);
+        Ok(())
+    })?;
+
+    Ok(relacoes)
+}
+
+/// Tamanho máximo, em bytes, de cada arquivo XML (isolado ou contido em um lote `.zip`).
+pub const TAMANHO_MAXIMO_DO_XML: u64 = 64 * 1024 * 1024;
+
+/// Lê o conteúdo do XML, interrompendo a leitura acima de `TAMANHO_MAXIMO_DO_XML`.
+fn ler_xml_limitado(leitor: impl Read, origem: &Path) -> SpedResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    leitor
+        .take(TAMANHO_MAXIMO_DO_XML + 1)
+        .read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 > TAMANHO_MAXIMO_DO_XML {
+        return Err(SpedError::XmlMuitoGrande {
+            arquivo: origem.to_path_buf(),
+            limite: TAMANHO_MAXIMO_DO_XML,
+        });
+    }
+
+    Ok(bytes)
+}
+
+/// Submete à função o conteúdo de um arquivo `.xml` ou de cada arquivo `.xml` contido
+/// em um lote `.zip`, um de cada vez: o lote não é retido integralmente em memória.
+///
+/// A origem de cada conteúdo é identificada por `<arquivo.xml>` ou `<lote.zip>/<arquivo.xml>`.
+/// O conteúdo é decodificado para UTF-8 conforme `--encoding` ou a declaração XML
+/// (ver `decodificar_xml`). Conteúdos acima de `TAMANHO_MAXIMO_DO_XML` interrompem a leitura.
+pub fn percorrer_conteudos_xml(
+    path: &Path,
+    codificacao: Codificacao,
+    mut processar: impl FnMut(PathBuf, String) -> SpedResult<()>,
+) -> SpedResult<()> {
+    if !tem_extensao(path, "zip") {
+        let bytes = ler_xml_limitado(File::open(path)?, path)?;
+        return processar(path.to_path_buf(), decodificar_xml(&bytes, codificacao)?);
+    }
+
+    let erro_zip = |source| SpedError::Zip {
+        source,
+        arquivo: path.to_path_buf(),
+    };
+
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(erro_zip)?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(erro_zip)?;
+
+        if !entry.is_file() || !entry.name().to_ascii_lowercase().ends_with(".xml") {
+            continue;
+        }
+
+        // Sem pré-alocação: o tamanho declarado no cabeçalho do zip não é confiável
+        let origem = path.join(entry.name());
+        let bytes = ler_xml_limitado(&mut entry, &origem)?;
+
+        processar(origem, decodificar_xml(&bytes, codificacao)?)?;
+    }
+
+    Ok(())
+}
+
+/// Extrai de um documento XML as chaves de `infCte/@Id`, `infNFe/chave` e `infCteComp/chCTe`.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::analisar_xml_de_cte;
+/// use std::path::Path;
+///
+/// let xml = r#"
+/// <cteProc xmlns="http://www.portalfiscal.inf.br/cte" versao="4.00">
+///   <CTe>
+///     <infCte Id="CTe35240112345678000190570010000000011000000017" versao="4.00">
+///       <infCTeNorm>
+///         <infDoc>
+///           <infNFe><chave>35240112345678000190550010000012341123456786</chave></infNFe>
+///         </infDoc>
+///       </infCTeNorm>
+///       <infCteComp><chCTe>35240112345678000190570010000000021000000022</chCTe></infCteComp>
+///     </infCte>
+///   </CTe>
+/// </cteProc>"#;
+///
+/// let relacoes = analisar_xml_de_cte(xml, Path::new("cte.xml")).unwrap();
+/// let cte = "35240112345678000190570010000000011000000017".parse().unwrap();
+///
+/// assert_eq!(relacoes.documentos, 1);
+/// assert_eq!(relacoes.cte_nfes[&cte].len(), 1);
+/// assert_eq!(relacoes.cte_complementar.len(), 1); // Cada par é armazenado uma única vez
+/// ```
+pub fn analisar_xml_de_cte(xml: &str, arquivo: &Path) -> SpedResult<RelacoesCte> {
+    let doc = roxmltree::Document::parse(xml).map_err(|source| SpedError::Xml {
+        source,
+        arquivo: arquivo.to_path_buf(),
+    })?;
+
+    let mut relacoes = RelacoesCte::default();
+
+    // Um lote pode conter mais de um CT-e no mesmo documento
+    for inf_cte in doc.descendants().filter(|n| n.has_tag_name("infCte")) {
+        let linha = |node: roxmltree::Node| doc.text_pos_at(node.range().start).row as usize;
+
+        // Chave válida (44 posições e DV correto); DVs inválidos vão para relatório próprio
+        let mut validar = |texto: &str, node: roxmltree::Node, modelo: &str| {
+            let chave = texto.trim().parse::<ChaveAcesso>().ok()?;
+
+            if !chave.dv_valido() {
+                relacoes.invalidas.push(ChaveInvalida {
+                    chave,
+                    arquivo: arquivo.to_path_buf(),
+                    linha: linha(node),
+                });
+                return None;
+            }
+
+            chave.eh_modelo(modelo).then_some(chave)
+        };
+
+        // O atributo Id tem o formato "CTe" + chave de 44 posições
+        let Some(cte) = inf_cte
+            .attribute("Id")
+            .and_then(|id| validar(id.trim_start_matches("CTe"), inf_cte, "57"))
+        else {
+            continue;
+        };
+
+        let mut nfes = HashSet::new();
+        let mut complementares = HashSet::new();
+
+        for node in inf_cte.descendants().filter(|n| n.is_element()) {
+            let parent = node.parent_element().map(|p| p.tag_name().name());
+            let texto = node.text().unwrap_or_default();
+
+            match (parent, node.tag_name().name()) {
+                (Some("infNFe"), "chave") => nfes.extend(validar(texto, node, "55")),
+                // "chave" em leiautes anteriores à versão 3.00
+                (Some("infCteComp"), "chCTe" | "chave") => {
+                    complementares.extend(validar(texto, node, "57"))
+                }
+                _ => {}
+            }
+        }
+
+        relacoes.documentos += 1;
+
+        if !nfes.is_empty() {
+            relacoes.cte_nfes.entry(cte).or_default().extend(nfes);
+        }
+
+        // Cada par é armazenado uma única vez: o sentido inverso é obtido do `IndiceDeRelacoes`
+        complementares.retain(|&comp| comp != cte);
+        if !complementares.is_empty() {
+            relacoes
+                .cte_complementar
+                .entry(cte)
+                .or_default()
+                .extend(complementares);
+        }
+    }
+
+    Ok(relacoes)
+}
+
+/// Grava a relação CTe -> NFes no formato lido por `ler_todas_as_nfes_deste_cte`.
+///
+/// Cada linha contém a chave do CTe seguida das chaves das NFes, separadas por espaço.
+pub fn gravar_cte_nfes(cte_nfes: &KeyMap, path: &Path) -> SpedResult<()> {
+    let linhas: BTreeSet<Vec<ChaveAcesso>> = cte_nfes
+        .iter()
+        .map(|(cte, nfes)| {
+            let nfes: BTreeSet<&ChaveAcesso> = nfes.iter().collect();
+            std::iter::once(cte).chain(nfes).copied().collect()
+        })
+        .collect();
+
+    gravar_linhas(&linhas, path)
+}
+
+/// Grava a relação CTe -> CTe complementar no formato lido por `ler_chave_complementar_deste_cte`.
+///
+/// Cada linha contém um único par de chaves: o par informado nos dois sentidos é gravado uma única vez.
+pub fn gravar_cte_complementar(cte_complementar: &KeyMap, path: &Path) -> SpedResult<()> {
+    let linhas: BTreeSet<Vec<ChaveAcesso>> = cte_complementar
+        .iter()
+        .flat_map(|(&cte, comps)| {
+            comps
+                .iter()
+                .map(move |&comp| vec![cte.min(comp), cte.max(comp)])
+        })
+        .collect();
+
+    gravar_linhas(&linhas, path)
+}
+
+fn gravar_linhas(linhas: &BTreeSet<Vec<ChaveAcesso>>, path: &Path) -> SpedResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    for linha in linhas {
+        let chaves: Vec<String> = linha.iter().map(ToString::to_string).collect();
+        writeln!(writer, "{}", chaves.join(" "))?;
+    }
+
+    writer.flush()?;
+
+    println!(
+        " ---> Novo arquivo de relações: <{}> ({} linhas)",
+        path.display(),
+        fmt_milhares(linhas.len())
+    );
+
+    Ok(())
+}
@@ -0,0 +1,611 @@
+use rayon::prelude::*;
+use roxmltree::Node;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    COLUNA_MOTIVO, ChaveAcesso, Codificacao, Config, InfoDocs, InfoEfd, KeyMap, LinhaDoc,
+    RE_MULTISPACE, SpedError, SpedResult, abrir_decodificado, buscar_arquivos_xml_e_zip,
+    classificar_linha, fmt_milhares, get_modelo_documentos_fiscais, percorrer_conteudos_xml,
+};
+
+/// Campos lógicos de `COLUNAS_DOC` e respectivos valores.
+pub type CamposNfe = HashMap<&'static str, String>;
+
+/// NF-e extraída de um documento XML: campos da nota e campos de cada item (`det`).
+///
+/// Os campos da nota são armazenados uma única vez e compartilhados pelos itens.
+#[derive(Debug, Default)]
+pub struct NotaNfe {
+    pub campos: CamposNfe,
+    /// Linha do `det` no arquivo XML e campos próprios do item.
+    pub itens: Vec<(usize, CamposNfe)>,
+}
+
+impl NotaNfe {
+    /// Valor do campo lógico no item ou, na sua ausência, na nota.
+    pub fn campo<'a>(&'a self, item: &'a CamposNfe, nome: &str) -> &'a str {
+        item.get(nome)
+            .or_else(|| self.campos.get(nome))
+            .map_or("", String::as_str)
+    }
+}
+
+/// Processa um diretório de arquivos XML de NF-e (`NFe` ou `nfeProc`) ou lotes `.zip`
+/// como fonte de Documentos Fiscais.
+///
+/// Cada item (`det`) torna-se uma linha com as mesmas colunas dos arquivos CSV
+/// do ReceitaNet-BX (`COLUNAS_DOC`), submetida ao mesmo filtro e gravada no mesmo
+/// arquivo temporário consolidado por `merge_files`.
+///
+/// Cada arquivo XML (ou lote `.zip`) é filtrado na própria thread e gravado em um
+/// arquivo temporário próprio, concatenados ao final: apenas um documento XML por
+/// thread permanece em memória, e não todos os itens do diretório.
+///
+/// A NF-e é considerada cancelada pelo protocolo de autorização (`protNFe/cStat` 101
+/// ou 151) ou pelo evento de cancelamento (`procEventoNFe`) contido em qualquer arquivo
+/// do diretório (ver `extrair_cancelamentos_nfe`).
+pub fn process_xml_nfe_dir(dir: &Path, config: &Config, filter: &InfoEfd) -> SpedResult<InfoDocs> {
+    // 1. Cabeçalho: o mesmo do primeiro CSV (para alinhar as colunas) ou o padrão de COLUNAS_DOC
+    let headers = cabecalho_dos_documentos(config)?;
+
+    // Coluna do cabeçalho -> campo lógico
+    let campos: Vec<Option<&'static str>> = headers
+        .iter()
+        .map(|coluna| {
+            config
+                .colunas_doc
+                .iter()
+                .find(|(_, nome)| **nome == coluna)
+                .map(|(campo, _)| *campo)
+        })
+        .collect();
+
+    // 2. Eventos de cancelamento: usualmente gravados em arquivos distintos dos da NF-e
+    let arquivos = buscar_arquivos_xml_e_zip(dir)?;
+    let canceladas = ler_cancelamentos_nfe(&arquivos, config.codificacao);
+
+    // 3. Leitura, filtro e gravação paralelos (um arquivo temporário por arquivo XML)
+    let info = arquivos
+        .par_iter()
+        .map(|path| {
+            processar_arquivo_xml(path, &campos, &canceladas, config, filter).unwrap_or_else(|e| {
+                eprintln!(" [ERRO] Arquivo <{}>: {}", path.display(), e);
+                // Itens já gravados do arquivo com erro são descartados
+                let _ = fs::remove_file(config.to_hash(path));
+                InfoDocs::default()
+            })
+        })
+        .reduce(InfoDocs::default, InfoDocs::merge);
+
+    // 4. Concatenação dos arquivos temporários no arquivo temporário do diretório
+    let mut temp_file = BufWriter::with_capacity(1024 * 1024, File::create(config.to_hash(dir))?);
+
+    // Grava cabeçalho apenas se for o primeiro arquivo
+    if config.arquivos_csv.first().map(PathBuf::as_path) == Some(dir) {
+        let mut headers = headers.clone();
+        headers.push_field(COLUNA_MOTIVO);
+
+        let mut wtr = csv::WriterBuilder::new()
+            .delimiter(b';')
+            .from_writer(&mut temp_file);
+        wtr.write_record(&headers)?;
+        wtr.flush()?;
+    }
+
+    for path in &arquivos {
+        let temp_path = config.to_hash(path);
+
+        if let Ok(mut parcial) = File::open(&temp_path) {
+            io::copy(&mut parcial, &mut temp_file)?;
+            fs::remove_file(&temp_path)?;
+        }
+    }
+
+    temp_file.flush()?;
+    Ok(info)
+}
+
+/// Filtra os itens das NF-e de um arquivo `.xml` (ou lote `.zip`) e grava as linhas
+/// retidas no arquivo temporário do próprio arquivo.
+fn processar_arquivo_xml(
+    path: &Path,
+    campos: &[Option<&'static str>],
+    canceladas: &HashSet<ChaveAcesso>,
+    config: &Config,
+    filter: &InfoEfd,
+) -> SpedResult<InfoDocs> {
+    let temp_file = File::create(config.to_hash(path))?;
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(BufWriter::new(temp_file));
+
+    let mut info = InfoDocs::default();
+
+    percorrer_conteudos_xml(path, config.codificacao, |origem, xml| {
+        let doc = roxmltree::Document::parse(&xml).map_err(|source| SpedError::Xml {
+            source,
+            arquivo: origem.clone(),
+        })?;
+
+        for mut nota in extrair_itens_nfe(&doc) {
+            let chave = nota.campos["chave44_digitos"].parse::<ChaveAcesso>().ok();
+            if chave.is_some_and(|chave| canceladas.contains(&chave)) {
+                nota.campos.insert("nota_cancelada", "Sim".to_string());
+            }
+
+            for (linha_xml, item) in &nota.itens {
+                info.total_de_itens += 1;
+
+                let campo = |nome: &str| nota.campo(item, nome);
+
+                let linha = LinhaDoc {
+                    chave,
+                    // Os itens de NF-e não contêm a Coluna 2 (regra b)
+                    chave_nfe: None,
+                    cnpj: campo("cnpj_participante"),
+                    numero: campo("num_doc_fiscal"),
+                    cancelada: Some(campo("nota_cancelada")),
+                    ocorrencia: (origem.as_path(), *linha_xml),
+                    num_item: campo("num_item"),
+                    valor_total: campo("valor_total"),
+                    valores: match config.conciliacao {
+                        Some(_) => config
+                            .escrituracao
+                            .valores_conciliados()
+                            .iter()
+                            .map(|valor| valor.campo_doc.map(campo).unwrap_or_default())
+                            .collect(),
+                        None => Vec::new(),
+                    },
+                };
+
+                let Some(motivo) = classificar_linha(&linha, config, filter, &mut info) else {
+                    continue;
+                };
+
+                let mut record: Vec<String> = campos
+                    .iter()
+                    .map(|campo_logico| {
+                        let valor = campo_logico.map(campo).unwrap_or_default();
+                        RE_MULTISPACE.replace_all(valor, " ").into_owned()
+                    })
+                    .collect();
+
+                record.push(motivo.to_string());
+
+                wtr.write_record(&record)?;
+            }
+        }
+
+        Ok(())
+    })?;
+
+    wtr.flush()?;
+    Ok(info)
+}
+
+/// Chaves das NF-e canceladas por evento nos arquivos `.xml` (ou lotes `.zip`).
+///
+/// Apenas os documentos com evento (`tpEvento`) são analisados. Os erros de leitura
+/// são informados na leitura das NF-e, que percorre os mesmos arquivos.
+fn ler_cancelamentos_nfe(arquivos: &[PathBuf], codificacao: Codificacao) -> HashSet<ChaveAcesso> {
+    arquivos
+        .par_iter()
+        .map(|path| {
+            let mut canceladas = HashSet::new();
+
+            let _ = percorrer_conteudos_xml(path, codificacao, |origem, xml| {
+                if !xml.contains("tpEvento") {
+                    return Ok(());
+                }
+
+                let doc = roxmltree::Document::parse(&xml).map_err(|source| SpedError::Xml {
+                    source,
+                    arquivo: origem,
+                })?;
+                canceladas.extend(extrair_cancelamentos_nfe(&doc));
+                Ok(())
+            });
+
+            canceladas
+        })
+        .reduce(HashSet::new, |mut a, b| {
+            a.extend(b);
+            a
+        })
+}
+
+/// Extrai as chaves das NF-e canceladas pelos eventos de cancelamento do documento
+/// (`evento/infEvento`, `tpEvento` 110111 ou 110112: cancelamento por substituição).
+///
+/// Eventos cujo retorno (`retEvento/infEvento/cStat`) não é 135 ou 155 (evento
+/// registrado) são ignorados, assim como chaves com DV inválido.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::{ChaveAcesso, extrair_cancelamentos_nfe};
+///
+/// let xml = r#"
+/// <procEventoNFe xmlns="http://www.portalfiscal.inf.br/nfe" versao="1.00">
+///   <evento versao="1.00">
+///     <infEvento Id="ID1101113524011234567800019055001000001234112345678601">
+///       <chNFe>35240112345678000190550010000012341123456786</chNFe>
+///       <tpEvento>110111</tpEvento>
+///       <detEvento versao="1.00"><descEvento>Cancelamento</descEvento></detEvento>
+///     </infEvento>
+///   </evento>
+///   <retEvento versao="1.00">
+///     <infEvento><cStat>135</cStat><tpEvento>110111</tpEvento></infEvento>
+///   </retEvento>
+/// </procEventoNFe>"#;
+///
+/// let doc = roxmltree::Document::parse(xml).unwrap();
+/// let nfe: ChaveAcesso = "35240112345678000190550010000012341123456786".parse().unwrap();
+///
+/// assert!(extrair_cancelamentos_nfe(&doc).contains(&nfe));
+///
+/// // Evento rejeitado: a NF-e não é considerada cancelada
+/// let rejeitado = xml.replace("<cStat>135</cStat>", "<cStat>573</cStat>");
+/// let doc = roxmltree::Document::parse(&rejeitado).unwrap();
+/// assert!(extrair_cancelamentos_nfe(&doc).is_empty());
+/// ```
+pub fn extrair_cancelamentos_nfe(doc: &roxmltree::Document) -> HashSet<ChaveAcesso> {
+    doc.descendants()
+        .filter(|n| n.has_tag_name("evento"))
+        .filter_map(|evento| {
+            let inf_evento = child(evento, "infEvento")?;
+
+            if !matches!(texto(inf_evento, &["tpEvento"]), Some("110111" | "110112")) {
+                return None;
+            }
+
+            // Retorno do evento (procEventoNFe): apenas eventos registrados
+            let c_stat = evento
+                .parent_element()
+                .and_then(|proc| child(proc, "retEvento"))
+                .and_then(|ret| texto(ret, &["infEvento", "cStat"]));
+            if c_stat.is_some_and(|c_stat| !matches!(c_stat, "135" | "155")) {
+                return None;
+            }
+
+            texto(inf_evento, &["chNFe"])?
+                .parse::<ChaveAcesso>()
+                .ok()
+                .filter(|chave| chave.dv_valido())
+        })
+        .collect()
+}
+
+/// Cabeçalho do primeiro arquivo CSV de Documentos Fiscais ou, na sua ausência,
+/// as colunas de `COLUNAS_DOC` em ordem alfabética.
+fn cabecalho_dos_documentos(config: &Config) -> SpedResult<csv::StringRecord> {
+    let primeiro_csv = config.arquivos_csv.iter().find(|path| path.is_file());
+
+    if let Some(path) = primeiro_csv {
+        let file = abrir_decodificado(path, config.codificacao)?;
+
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b';')
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(BufReader::new(file));
+
+        return Ok(rdr.headers()?.clone());
+    }
+
+    let mut colunas: Vec<&str> = config.colunas_doc.values().copied().collect();
+    colunas.sort_unstable();
+
+    Ok(csv::StringRecord::from(colunas))
+}
+
+/// Constrói a relação NFe -> NFes referenciadas (`ide/NFref/refNFe`) a partir dos
+/// arquivos XML de NF-e (ou lotes `.zip`) do diretório.
+///
+/// Devoluções, NF-e complementares e de ajuste referenciam a NF-e original: as
+/// relações permitem analisar as duas notas em conjunto.
+pub fn ler_referencias_dos_xmls_de_nfe(dir: &Path, codificacao: Codificacao) -> SpedResult<KeyMap> {
+    let arquivos = buscar_arquivos_xml_e_zip(dir)?;
+
+    let referencias = arquivos
+        .par_iter()
+        .map(|path| {
+            ler_referencias_nfe(path, codificacao)
+                .map_err(|e| {
+                    eprintln!(" [ERRO] Arquivo <{}>: {}", path.display(), e);
+                    e
+                })
+                .unwrap_or_default() // Se falhar, retorna resultado vazio
+        })
+        .reduce(KeyMap::new, |mut map_a, map_b| {
+            for (key, values) in map_b {
+                map_a.entry(key).or_default().extend(values);
+            }
+            map_a
+        });
+
+    println!(
+        "Encontrado {:>6} NFes com referências a outras NFes nos arquivos XML do diretório <{}>.",
+        fmt_milhares(referencias.len()),
+        dir.display(),
+    );
+
+    Ok(referencias)
+}
+
+fn ler_referencias_nfe(path: &Path, codificacao: Codificacao) -> SpedResult<KeyMap> {
+    let mut referencias = KeyMap::new();
+
+    percorrer_conteudos_xml(path, codificacao, |origem, xml| {
+        let doc = roxmltree::Document::parse(&xml).map_err(|source| SpedError::Xml {
+            source,
+            arquivo: origem,
+        })?;
+
+        for (nfe, refs) in extrair_referencias_nfe(&doc) {
+            referencias.entry(nfe).or_default().extend(refs);
+        }
+        Ok(())
+    })?;
+
+    Ok(referencias)
+}
+
+/// Extrai as chaves das NF-e referenciadas (`ide/NFref/refNFe`) por cada NF-e do documento.
+///
+/// Apenas chaves de NF-e (modelo 55) com DV válido são consideradas.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::{ChaveAcesso, extrair_referencias_nfe};
+///
+/// let xml = r#"
+/// <NFe xmlns="http://www.portalfiscal.inf.br/nfe">
+///   <infNFe Id="NFe35240112345678000190550010000000011000000010" versao="4.00">
+///     <ide>
+///       <finNFe>4</finNFe>
+///       <NFref><refNFe>35240112345678000190550010000012341123456786</refNFe></NFref>
+///     </ide>
+///   </infNFe>
+/// </NFe>"#;
+///
+/// let doc = roxmltree::Document::parse(xml).unwrap();
+/// let referencias = extrair_referencias_nfe(&doc);
+///
+/// let devolucao: ChaveAcesso = "35240112345678000190550010000000011000000010".parse().unwrap();
+/// let original: ChaveAcesso = "35240112345678000190550010000012341123456786".parse().unwrap();
+///
+/// assert!(referencias[&devolucao].contains(&original));
+/// ```
+pub fn extrair_referencias_nfe(doc: &roxmltree::Document) -> KeyMap {
+    let mut referencias = KeyMap::new();
+
+    let nfe_valida = |texto: &str| {
+        texto
+            .trim()
+            .trim_start_matches("NFe")
+            .parse::<ChaveAcesso>()
+            .ok()
+            .filter(|chave| chave.eh_modelo("55") && chave.dv_valido())
+    };
+
+    for inf_nfe in doc.descendants().filter(|n| n.has_tag_name("infNFe")) {
+        let Some(nfe) = inf_nfe.attribute("Id").and_then(nfe_valida) else {
+            continue;
+        };
+
+        let refs: Vec<ChaveAcesso> = child(inf_nfe, "ide")
+            .into_iter()
+            .flat_map(|ide| ide.children().filter(|n| n.has_tag_name("NFref")))
+            .filter_map(|nf_ref| texto(nf_ref, &["refNFe"]))
+            .filter_map(nfe_valida)
+            .filter(|&referenciada| referenciada != nfe)
+            .collect();
+
+        if !refs.is_empty() {
+            referencias.entry(nfe).or_default().extend(refs);
+        }
+    }
+
+    referencias
+}
+
+/// Converte as NF-e do documento e cada um dos seus itens (`det`) nos campos lógicos
+/// de `COLUNAS_DOC`.
+///
+/// Valores numéricos são gravados com vírgula decimal e a data de emissão no formato
+/// `DD/MM/AAAA`, como nos arquivos CSV do ReceitaNet-BX. Retorna também a linha de
+/// cada `det` no arquivo XML.
+///
+/// ```
+/// use reter_linhas_com_info_das_chaves::extrair_itens_nfe;
+///
+/// let xml = r#"
+/// <nfeProc xmlns="http://www.portalfiscal.inf.br/nfe" versao="4.00">
+///   <NFe>
+///     <infNFe Id="NFe35240112345678000190550010000012341123456786" versao="4.00">
+///       <ide><mod>55</mod><nNF>1234</nNF><dhEmi>2024-01-15T10:00:00-03:00</dhEmi></ide>
+///       <emit><CNPJ>12345678000190</CNPJ><xNome>Fornecedor</xNome></emit>
+///       <det nItem="1">
+///         <prod><xProd>Produto</xProd><NCM>10063021</NCM><CFOP>5102</CFOP><vProd>100.00</vProd></prod>
+///         <imposto>
+///           <PIS><PISAliq><CST>01</CST><pPIS>1.65</pPIS><vPIS>1.65</vPIS></PISAliq></PIS>
+///           <COFINS><COFINSAliq><CST>01</CST><pCOFINS>7.60</pCOFINS><vCOFINS>7.60</vCOFINS></COFINSAliq></COFINS>
+///         </imposto>
+///       </det>
+///     </infNFe>
+///   </NFe>
+/// </nfeProc>"#;
+///
+/// let doc = roxmltree::Document::parse(xml).unwrap();
+/// let notas = extrair_itens_nfe(&doc);
+/// let nota = &notas[0];
+/// let (_linha, item) = &nota.itens[0];
+///
+/// assert_eq!(nota.campo(item, "chave44_digitos"), "35240112345678000190550010000012341123456786");
+/// assert_eq!(nota.campo(item, "num_item"), "1");
+/// assert_eq!(nota.campo(item, "codigo_cfop"), "5102");
+/// assert_eq!(nota.campo(item, "valor_tributo_cofins"), "7,60");
+/// assert_eq!(nota.campo(item, "cst_pis_descr"), "01");
+/// assert_eq!(nota.campo(item, "dia_emissao_nota"), "15/01/2024");
+/// assert_eq!(nota.campo(item, "nota_cancelada"), "Não");
+/// ```
+pub fn extrair_itens_nfe(doc: &roxmltree::Document) -> Vec<NotaNfe> {
+    let mut notas = Vec::new();
+
+    for inf_nfe in doc.descendants().filter(|n| n.has_tag_name("infNFe")) {
+        let Some(chave) = inf_nfe
+            .attribute("Id")
+            .map(|id| id.trim_start_matches("NFe").to_string())
+        else {
+            continue;
+        };
+
+        // Situação da autorização: 101 e 151 indicam NF-e cancelada
+        // (os eventos de cancelamento são aplicados por `process_xml_nfe_dir`)
+        let nfe_proc = inf_nfe.ancestors().find(|n| n.has_tag_name("nfeProc"));
+        let c_stat = nfe_proc.and_then(|n| texto(n, &["protNFe", "infProt", "cStat"]));
+        let cancelada = if matches!(c_stat, Some("101" | "151")) {
+            "Sim"
+        } else {
+            "Não"
+        };
+
+        let modelo = texto(inf_nfe, &["ide", "mod"]).unwrap_or_default();
+        let emitente = texto(inf_nfe, &["emit", "CNPJ"]).or(texto(inf_nfe, &["emit", "CPF"]));
+        let destinatario = texto(inf_nfe, &["dest", "CNPJ"]).or(texto(inf_nfe, &["dest", "CPF"]));
+
+        let entrada_ou_saida = match texto(inf_nfe, &["ide", "tpNF"]) {
+            Some("0") => "Entrada",
+            Some("1") => "Saída",
+            _ => "",
+        };
+
+        // Campos da NF-e (comuns a todos os itens)
+        let mut nota = NotaNfe::default();
+        nota.campos.extend([
+            ("chave44_digitos", chave),
+            ("nota_cancelada", cancelada.to_string()),
+            (
+                "cnpj_contribuinte",
+                destinatario.unwrap_or_default().to_string(),
+            ),
+            ("nome_contribuinte", texto_de(inf_nfe, &["dest", "xNome"])),
+            (
+                "cnpj_participante",
+                emitente.unwrap_or_default().to_string(),
+            ),
+            ("nome_participante", texto_de(inf_nfe, &["emit", "xNome"])),
+            ("codigo_crt", texto_de(inf_nfe, &["emit", "CRT"])),
+            ("entrada_ou_saida", entrada_ou_saida.to_string()),
+            ("observacoes", texto_de(inf_nfe, &["infAdic", "infCpl"])),
+            ("descricao_nat_oper", texto_de(inf_nfe, &["ide", "natOp"])),
+            (
+                "modelo_descricao",
+                get_modelo_documentos_fiscais(modelo).to_string(),
+            ),
+            ("num_doc_fiscal", texto_de(inf_nfe, &["ide", "nNF"])),
+            (
+                "dia_emissao_nota",
+                texto(inf_nfe, &["ide", "dhEmi"])
+                    .or(texto(inf_nfe, &["ide", "dEmi"]))
+                    .map(formatar_data)
+                    .unwrap_or_default(),
+            ),
+            ("valor_total", valor(inf_nfe, &["total", "ICMSTot", "vNF"])),
+            (
+                "valor_seguro",
+                valor(inf_nfe, &["total", "ICMSTot", "vSeg"]),
+            ),
+        ]);
+
+        for det in inf_nfe.children().filter(|n| n.has_tag_name("det")) {
+            let imposto = |caminho: &[&str]| {
+                child(det, "imposto")
+                    .map(|node| valor(node, caminho))
+                    .unwrap_or_default()
+            };
+
+            let item = CamposNfe::from([
+                (
+                    "num_item",
+                    det.attribute("nItem").unwrap_or_default().to_string(),
+                ),
+                ("codigo_cfop", texto_de(det, &["prod", "CFOP"])),
+                ("codigo_ncm", texto_de(det, &["prod", "NCM"])),
+                ("descricao_da_mercadoria", texto_de(det, &["prod", "xProd"])),
+                ("num_di", texto_de(det, &["prod", "DI", "nDI"])),
+                ("valor_proporcional", valor(det, &["prod", "vProd"])),
+                ("valor_descontos", valor(det, &["prod", "vDesc"])),
+                // Os grupos de PIS e COFINS variam conforme o CST (Aliq, Qtde, NT, Outr)
+                (
+                    "cst_pis_descr",
+                    texto_de(det, &["imposto", "PIS", "*", "CST"]),
+                ),
+                (
+                    "cst_cofins_descr",
+                    texto_de(det, &["imposto", "COFINS", "*", "CST"]),
+                ),
+                ("aliquota_pis", imposto(&["PIS", "*", "pPIS"])),
+                ("aliquota_cofins", imposto(&["COFINS", "*", "pCOFINS"])),
+                ("valor_tributo_pis", imposto(&["PIS", "*", "vPIS"])),
+                ("valor_tributo_cofins", imposto(&["COFINS", "*", "vCOFINS"])),
+                ("valor_tributo_ipi", imposto(&["IPI", "IPITrib", "vIPI"])),
+                ("valor_bc_iss", imposto(&["ISSQN", "vBC"])),
+                ("valor_tributo_iss", imposto(&["ISSQN", "vISSQN"])),
+                ("aliquota_icms", imposto(&["ICMS", "*", "pICMS"])),
+                ("valor_bc_icms", imposto(&["ICMS", "*", "vBC"])),
+                ("valor_icms", imposto(&["ICMS", "*", "vICMS"])),
+                ("valor_icms_sub", imposto(&["ICMS", "*", "vICMSST"])),
+            ]);
+
+            let linha = doc.text_pos_at(det.range().start).row as usize;
+            nota.itens.push((linha, item));
+        }
+
+        notas.push(nota);
+    }
+
+    notas
+}
+
+/// Primeiro elemento filho com o nome informado ("*" corresponde a qualquer elemento).
+fn child<'a, 'input>(node: Node<'a, 'input>, nome: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.is_element() && (nome == "*" || n.has_tag_name(nome)))
+}
+
+/// Texto do elemento obtido percorrendo o caminho de elementos filhos.
+fn texto<'a>(node: Node<'a, '_>, caminho: &[&str]) -> Option<&'a str> {
+    caminho
+        .iter()
+        .try_fold(node, |atual, nome| child(atual, nome))?
+        .text()
+        .map(str::trim)
+}
+
+fn texto_de(node: Node, caminho: &[&str]) -> String {
+    texto(node, caminho).unwrap_or_default().to_string()
+}
+
+/// Valor numérico com vírgula decimal (padrão dos arquivos CSV do ReceitaNet-BX).
+fn valor(node: Node, caminho: &[&str]) -> String {
+    texto(node, caminho)
+        .map(|v| v.replace('.', ","))
+        .unwrap_or_default()
+}
+
+/// Converte `AAAA-MM-DD` (ou `AAAA-MM-DDThh:mm:ss-03:00`) em `DD/MM/AAAA`.
+fn formatar_data(data: &str) -> String {
+    match data
+        .get(..10)
+        .map(|d| d.split('-').collect::<Vec<_>>())
+        .as_deref()
+    {
+        Some([ano, mes, dia]) => format!("{dia}/{mes}/{ano}"),
+        _ => data.to_string(),
+    }
+}